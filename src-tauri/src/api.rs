@@ -0,0 +1,107 @@
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+use crate::registry::DownloadRecord;
+use crate::state::AppState;
+
+/// Versioned REST API served by the bridge under `/api/v1`. Every route calls
+/// the same code as the matching Tauri command; responses are JSON.
+pub fn handle(app: &AppHandle, method: &str, path: &str) -> (u16, String) {
+    let path = path.split('?').next().unwrap_or_default();
+    let Some(rest) = path.strip_prefix("/api/v1/downloads") else {
+        return error(404, "Not Found");
+    };
+    let parts: Vec<&str> = rest.split('/').filter(|p| !p.is_empty()).collect();
+    match (method, parts.as_slice()) {
+        ("GET", []) => match crate::registry::list(&app.state::<AppState>()) {
+            Ok(records) => json(200, &records),
+            Err(e) => error(500, &e),
+        },
+        ("GET", [id]) => match find(app, id) {
+            Some(record) => json(200, &record),
+            None => error(404, "Unknown download"),
+        },
+        ("POST", [id, action]) => {
+            if find(app, id).is_none() {
+                return error(404, "Unknown download");
+            }
+            let res = match *action {
+                "pause" => pause(app, id),
+                "resume" => resume(app, id),
+                "cancel" => cancel(app, id),
+                _ => return error(404, "Not Found"),
+            };
+            match res {
+                Ok(()) => json(202, &serde_json::json!({ "id": id })),
+                Err(e) => error(409, &e),
+            }
+        }
+        ("DELETE", [id]) => {
+            if find(app, id).is_none() {
+                return error(404, "Unknown download");
+            }
+            let state = app.state::<AppState>();
+            let res = tauri::async_runtime::block_on(crate::commands::core::delete_download(
+                app.clone(),
+                state,
+                id.to_string(),
+            ));
+            match res {
+                Ok(()) => json(200, &serde_json::json!({ "id": id })),
+                Err(e) => error(409, &e),
+            }
+        }
+        _ => error(405, "Method Not Allowed"),
+    }
+}
+
+fn pause(app: &AppHandle, id: &str) -> Result<(), String> {
+    crate::engine::http::pause(&app.state::<AppState>(), id)
+}
+
+fn cancel(app: &AppHandle, id: &str) -> Result<(), String> {
+    let state = app.state::<AppState>();
+    tauri::async_runtime::block_on(crate::commands::core::cancel_download(
+        app.clone(),
+        state,
+        id.to_string(),
+    ))
+}
+
+/// A resume runs until the download stops, so only the preconditions are
+/// checked here and the transfer continues in the background.
+fn resume(app: &AppHandle, id: &str) -> Result<(), String> {
+    let state = app.state::<AppState>();
+    if state
+        .downloads
+        .lock()
+        .map_err(|_| "State poisoned")?
+        .is_running(id)
+    {
+        return Err("Download is already running".into());
+    }
+    crate::sidecar::load(&state.dirs, id)?;
+    let app = app.clone();
+    let id = id.to_string();
+    tauri::async_runtime::spawn(async move {
+        let state = app.state::<AppState>();
+        let _ = crate::commands::http::resume_download(app.clone(), state, id).await;
+    });
+    Ok(())
+}
+
+fn find(app: &AppHandle, id: &str) -> Option<DownloadRecord> {
+    let records = crate::registry::list(&app.state::<AppState>()).ok()?;
+    records.into_iter().find(|r| r.id == id)
+}
+
+fn json<T: Serialize>(status: u16, body: &T) -> (u16, String) {
+    match serde_json::to_string(body) {
+        Ok(body) => (status, body),
+        Err(e) => error(500, &format!("Serialize error: {}", e)),
+    }
+}
+
+fn error(status: u16, message: &str) -> (u16, String) {
+    (status, serde_json::json!({ "error": message }).to_string())
+}
@@ -0,0 +1,3 @@
+fn main() {
+    std::process::exit(any_download_manager_lib::cli::main());
+}
@@ -0,0 +1,224 @@
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::Digest;
+use tokio::time::{Duration, Instant};
+
+use crate::engine::events::Events;
+use crate::error::DownloadError;
+use crate::payloads::VerifyingPayload;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Algorithm {
+    Md5,
+    Sha1,
+    Sha256,
+    Sha512,
+    Blake3,
+}
+
+impl Algorithm {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s.trim().to_ascii_lowercase().replace('-', "").as_str() {
+            "md5" => Ok(Self::Md5),
+            "sha1" => Ok(Self::Sha1),
+            "sha256" => Ok(Self::Sha256),
+            "sha512" => Ok(Self::Sha512),
+            "blake3" => Ok(Self::Blake3),
+            other => Err(format!("Unsupported checksum algorithm: {}", other)),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Md5 => "md5",
+            Self::Sha1 => "sha1",
+            Self::Sha256 => "sha256",
+            Self::Sha512 => "sha512",
+            Self::Blake3 => "blake3",
+        }
+    }
+
+    /// Length of the algorithm's digest in hex digits.
+    pub fn hex_len(self) -> usize {
+        match self {
+            Self::Md5 => 32,
+            Self::Sha1 => 40,
+            Self::Sha256 | Self::Blake3 => 64,
+            Self::Sha512 => 128,
+        }
+    }
+}
+
+/// Published hash a download must match, written as `algo:hex` (e.g.
+/// `sha256:9f86d0…`). A bare hex digest is accepted when its length identifies
+/// the algorithm; 64 hex digits are taken as SHA-256.
+#[derive(Clone)]
+pub struct ExpectedChecksum {
+    pub algorithm: Algorithm,
+    pub hex: String,
+}
+
+impl ExpectedChecksum {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let s = s.trim();
+        let (algorithm, hex) = match s.split_once(':') {
+            Some((algo, hex)) => (Algorithm::parse(algo)?, hex.trim()),
+            None => {
+                let algo = match s.len() {
+                    32 => Algorithm::Md5,
+                    40 => Algorithm::Sha1,
+                    64 => Algorithm::Sha256,
+                    128 => Algorithm::Sha512,
+                    _ => return Err("Cannot infer checksum algorithm; use algo:hex".into()),
+                };
+                (algo, s)
+            }
+        };
+        if hex.is_empty() || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err("Checksum must be a hex string".into());
+        }
+        if hex.len() != algorithm.hex_len() {
+            return Err(format!(
+                "A {} checksum has {} hex digits, not {}",
+                algorithm.name(),
+                algorithm.hex_len(),
+                hex.len()
+            ));
+        }
+        Ok(Self {
+            algorithm,
+            hex: hex.to_ascii_lowercase(),
+        })
+    }
+}
+
+enum Hasher {
+    Md5(md5::Md5),
+    Sha1(sha1::Sha1),
+    Sha256(sha2::Sha256),
+    Sha512(sha2::Sha512),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    fn new(algorithm: Algorithm) -> Self {
+        match algorithm {
+            Algorithm::Md5 => Self::Md5(md5::Md5::new()),
+            Algorithm::Sha1 => Self::Sha1(sha1::Sha1::new()),
+            Algorithm::Sha256 => Self::Sha256(sha2::Sha256::new()),
+            Algorithm::Sha512 => Self::Sha512(sha2::Sha512::new()),
+            Algorithm::Blake3 => Self::Blake3(Box::new(blake3::Hasher::new())),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Md5(h) => h.update(data),
+            Self::Sha1(h) => h.update(data),
+            Self::Sha256(h) => h.update(data),
+            Self::Sha512(h) => h.update(data),
+            Self::Blake3(h) => {
+                h.update(data);
+            }
+        }
+    }
+
+    fn finalize_hex(self) -> String {
+        let bytes: Vec<u8> = match self {
+            Self::Md5(h) => h.finalize().to_vec(),
+            Self::Sha1(h) => h.finalize().to_vec(),
+            Self::Sha256(h) => h.finalize().to_vec(),
+            Self::Sha512(h) => h.finalize().to_vec(),
+            Self::Blake3(h) => h.finalize().as_bytes().to_vec(),
+        };
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+/// Hash `path` in fixed-size blocks, emitting `download_verifying` progress
+/// under `id` roughly every 400ms. Runs on the blocking pool.
+pub async fn hash_file(
+    events: &Events,
+    id: &str,
+    path: &Path,
+    algorithm: Algorithm,
+) -> Result<String, DownloadError> {
+    let events = events.clone();
+    let id = id.to_string();
+    let path: PathBuf = path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let mut file = std::fs::File::open(&path).map_err(|e| DownloadError::io("Open file", e))?;
+        let total = file.metadata().map(|m| m.len()).unwrap_or(0);
+        let mut hasher = Hasher::new(algorithm);
+        let mut buf = vec![0u8; 1024 * 1024];
+        let mut processed = 0u64;
+        let mut last_emit = Instant::now();
+        loop {
+            let n = file
+                .read(&mut buf)
+                .map_err(|e| DownloadError::io("Read", e))?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+            processed += n as u64;
+            if last_emit.elapsed() >= Duration::from_millis(400) {
+                let payload = VerifyingPayload {
+                    id: id.clone(),
+                    algorithm: algorithm.name().into(),
+                    processed,
+                    total,
+                };
+                events.verifying(&payload);
+                last_emit = Instant::now();
+            }
+        }
+        let payload = VerifyingPayload {
+            id,
+            algorithm: algorithm.name().into(),
+            processed,
+            total,
+        };
+        events.verifying(&payload);
+        Ok(hasher.finalize_hex())
+    })
+    .await
+    .map_err(|e| DownloadError::from(format!("Join error: {}", e)))?
+}
+
+/// Hash a finished download and compare it with the expected digest.
+pub async fn verify(
+    events: &Events,
+    id: &str,
+    path: &Path,
+    expected: &ExpectedChecksum,
+) -> Result<(), DownloadError> {
+    let actual = hash_file(events, id, path, expected.algorithm).await?;
+    if actual != expected.hex {
+        return Err(DownloadError::checksum_mismatch(
+            expected.algorithm.name(),
+            &expected.hex,
+            &actual,
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_digests_of_the_wrong_length() {
+        let sha256 = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+        assert!(ExpectedChecksum::parse(&format!("sha256:{}", sha256)).is_ok());
+        assert!(ExpectedChecksum::parse(&format!("blake3:{}", sha256)).is_ok());
+        assert!(ExpectedChecksum::parse(sha256).is_ok_and(|c| c.algorithm == Algorithm::Sha256));
+        assert!(ExpectedChecksum::parse("sha256:abcd").is_err());
+        assert!(ExpectedChecksum::parse(&format!("md5:{}", sha256)).is_err());
+        assert!(ExpectedChecksum::parse(&format!("sha512:{}", sha256)).is_err());
+    }
+}
@@ -0,0 +1,285 @@
+//! `adm`: drive the download engine from a terminal (CI jobs, SSH sessions)
+//! without opening the app window.
+
+use std::sync::{Arc, Mutex};
+
+use console::style;
+use indicatif::{ProgressBar, ProgressStyle};
+
+use crate::engine::events::{DownloadEvents, Events};
+use crate::error::ErrorKind;
+use crate::payloads::{
+    CanceledPayload, CompletedPayload, DownloadRequest, FailedPayload, PausedPayload,
+    ProgressPayload, ResumedPayload, StartedPayload, VerifyingPayload,
+};
+use crate::registry::DownloadStatus;
+use crate::state::AppState;
+
+const USAGE: &str = "Usage:
+  adm get <url> [-c <connections>] [-o <dir>] [-n <file name>] [--checksum <algo:hex>]
+  adm list
+  adm resume <id>";
+
+enum Command {
+    Get(Box<DownloadRequest>),
+    List,
+    Resume(String),
+}
+
+/// Entry point of the `adm` binary; returns the process exit code.
+pub fn main() -> i32 {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let command = match parse(&args) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("{}\n\n{}", style(e).red(), USAGE);
+            return 2;
+        }
+    };
+    let runtime = match tokio::runtime::Runtime::new() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("{}", style(format!("Runtime error: {}", e)).red());
+            return 1;
+        }
+    };
+    runtime.block_on(run(command))
+}
+
+fn parse(args: &[String]) -> Result<Command, String> {
+    match args.first().map(String::as_str) {
+        Some("get") => {
+            let mut request = DownloadRequest {
+                url: String::new(),
+                threads: crate::queue::DEFAULT_THREADS,
+                dest_dir: None,
+                file_name: None,
+                expected_checksum: None,
+                options: Default::default(),
+                resource: None,
+            };
+            let mut rest = args[1..].iter();
+            while let Some(arg) = rest.next() {
+                let mut value = || {
+                    rest.next()
+                        .cloned()
+                        .ok_or_else(|| format!("Missing value for {}", arg))
+                };
+                match arg.as_str() {
+                    "-c" | "--connections" => {
+                        request.threads = value()?
+                            .parse()
+                            .map_err(|_| "Connections must be a number from 1 to 32".to_string())?;
+                    }
+                    "-o" | "--output" => request.dest_dir = Some(value()?),
+                    "-n" | "--name" => request.file_name = Some(value()?),
+                    "--checksum" => request.expected_checksum = Some(value()?),
+                    flag if flag.starts_with('-') => {
+                        return Err(format!("Unknown option {}", flag));
+                    }
+                    url if request.url.is_empty() => request.url = url.to_string(),
+                    extra => return Err(format!("Unexpected argument {}", extra)),
+                }
+            }
+            if request.url.is_empty() {
+                return Err("Missing URL".into());
+            }
+            Ok(Command::Get(Box::new(request)))
+        }
+        Some("list") => Ok(Command::List),
+        Some("resume") => match args.get(1) {
+            Some(id) => Ok(Command::Resume(id.clone())),
+            None => Err("Missing download id".into()),
+        },
+        Some(other) => Err(format!("Unknown command {}", other)),
+        None => Err("Missing command".into()),
+    }
+}
+
+async fn run(command: Command) -> i32 {
+    let state = Arc::new(AppState::default());
+    let progress = Arc::new(TerminalProgress::default());
+    let events: Events = progress.clone();
+    let res = match command {
+        Command::List => {
+            list(&state);
+            return 0;
+        }
+        Command::Get(request) => {
+            pause_on_ctrl_c(&state);
+            crate::engine::http::run_download(&events, &state, None, *request).await
+        }
+        Command::Resume(id) => {
+            pause_on_ctrl_c(&state);
+            crate::engine::http::run_resume(&events, &state, id).await
+        }
+    };
+    match res {
+        Ok(path) => {
+            println!("{} {}", style("Saved").green().bold(), path);
+            0
+        }
+        Err(e) if e.kind == ErrorKind::Paused => {
+            if let Some(id) = progress.id() {
+                println!("Paused. Continue with: adm resume {}", id);
+            }
+            130
+        }
+        Err(e) => {
+            eprintln!("{} {}", style("Failed").red().bold(), e);
+            1
+        }
+    }
+}
+
+/// Ctrl-C pauses instead of killing the process, so the sidecar is saved and
+/// the download can be resumed later.
+fn pause_on_ctrl_c(state: &Arc<AppState>) {
+    let state = state.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok()
+            && let Ok(downloads) = state.downloads.lock()
+        {
+            for id in downloads.running_ids() {
+                let _ = downloads.pause(&id);
+            }
+        }
+    });
+}
+
+fn list(state: &AppState) {
+    let records = crate::registry::list(state).unwrap_or_default();
+    if records.is_empty() {
+        println!("No queued or resumable downloads.");
+        return;
+    }
+    println!(
+        "{}",
+        style(format!(
+            "{:<18} {:<8} {:>21}  {}",
+            "ID", "STATUS", "PROGRESS", "FILE"
+        ))
+        .bold()
+    );
+    for record in records {
+        let progress = match (record.status, record.total) {
+            (DownloadStatus::Queued, _) => "-".to_string(),
+            (_, Some(total)) => {
+                format!("{} / {}", human_bytes(record.received), human_bytes(total))
+            }
+            (_, None) => human_bytes(record.received),
+        };
+        let file = if record.file_name.is_empty() {
+            record.url
+        } else {
+            std::path::Path::new(&record.dest_dir)
+                .join(&record.file_name)
+                .display()
+                .to_string()
+        };
+        println!(
+            "{:<18} {:<8} {:>21}  {}",
+            record.id,
+            record.status.as_str(),
+            progress,
+            file
+        );
+    }
+}
+
+fn human_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+/// Renders the engine's events as a single progress bar.
+#[derive(Default)]
+struct TerminalProgress {
+    bar: Mutex<Option<ProgressBar>>,
+    id: Mutex<Option<String>>,
+}
+
+impl TerminalProgress {
+    fn id(&self) -> Option<String> {
+        self.id.lock().ok()?.clone()
+    }
+
+    fn start(&self, id: &str, file_name: &str, received: u64, total: Option<u64>) {
+        let bar = match total {
+            Some(total) => ProgressBar::new(total).with_style(
+                ProgressStyle::default_bar()
+                    .template("{msg} [{bar:30}] {bytes}/{total_bytes} {bytes_per_sec} eta {eta}")
+                    .progress_chars("=> "),
+            ),
+            None => ProgressBar::new_spinner().with_style(
+                ProgressStyle::default_spinner()
+                    .template("{spinner} {msg} {bytes} {bytes_per_sec}"),
+            ),
+        };
+        bar.set_message(file_name.to_string());
+        bar.set_position(received);
+        if let Ok(mut slot) = self.id.lock() {
+            *slot = Some(id.to_string());
+        }
+        if let Ok(mut slot) = self.bar.lock() {
+            *slot = Some(bar);
+        }
+    }
+
+    fn with_bar(&self, f: impl FnOnce(&ProgressBar)) {
+        if let Ok(slot) = self.bar.lock()
+            && let Some(bar) = slot.as_ref()
+        {
+            f(bar);
+        }
+    }
+}
+
+impl DownloadEvents for TerminalProgress {
+    fn started(&self, payload: &StartedPayload) {
+        self.start(&payload.id, &payload.file_name, 0, payload.total);
+    }
+
+    fn resumed(&self, payload: &ResumedPayload) {
+        self.start(
+            &payload.id,
+            &payload.file_name,
+            payload.received,
+            payload.total,
+        );
+    }
+
+    fn progress(&self, payload: &ProgressPayload) {
+        self.with_bar(|bar| bar.set_position(payload.received));
+    }
+
+    fn verifying(&self, payload: &VerifyingPayload) {
+        self.with_bar(|bar| {
+            bar.set_message(format!("verifying {}", payload.algorithm));
+            bar.set_length(payload.total);
+            bar.set_position(payload.processed);
+        });
+    }
+
+    fn completed(&self, _payload: &CompletedPayload) {
+        self.with_bar(|bar| bar.finish_with_message("done"));
+    }
+
+    fn paused(&self, _payload: &PausedPayload) {
+        self.with_bar(|bar| bar.abandon_with_message("paused"));
+    }
+
+    fn canceled(&self, _payload: &CanceledPayload) {
+        self.with_bar(|bar| bar.abandon_with_message("canceled"));
+    }
+
+    fn failed(&self, _payload: &FailedPayload) {
+        self.with_bar(|bar| bar.abandon_with_message("failed"));
+    }
+}
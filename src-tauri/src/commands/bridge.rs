@@ -0,0 +1,66 @@
+use tauri::State;
+
+use crate::pairing::BridgePairing;
+use crate::payloads::{BridgeAddPayload, BridgeStatus};
+use crate::state::AppState;
+
+/// Token and allow-listed extension ids, for showing the pairing details in the UI.
+#[tauri::command]
+pub async fn get_bridge_pairing(state: State<'_, AppState>) -> Result<BridgePairing, String> {
+    let pairing = state.pairing.lock().map_err(|_| "State poisoned")?;
+    Ok(pairing.clone())
+}
+
+/// New token for the bridge; every paired extension has to be approved again.
+#[tauri::command]
+pub async fn regenerate_bridge_token(state: State<'_, AppState>) -> Result<String, String> {
+    let mut pairing = state.pairing.lock().map_err(|_| "State poisoned")?;
+    let token = pairing.regenerate();
+    pairing.save(&state.dirs)?;
+    Ok(token)
+}
+
+/// Approve an extension (usually after a `bridge-pair-request` event) so its
+/// next `/pair` call receives the token.
+#[tauri::command]
+pub async fn allow_bridge_extension(
+    state: State<'_, AppState>,
+    extension_id: String,
+) -> Result<(), String> {
+    let extension_id = extension_id.trim().to_string();
+    if extension_id.is_empty() || extension_id.contains('/') {
+        return Err("Invalid extension id".into());
+    }
+    let mut pairing = state.pairing.lock().map_err(|_| "State poisoned")?;
+    if !pairing.allowed_extensions.contains(&extension_id) {
+        pairing.allowed_extensions.push(extension_id);
+        pairing.save(&state.dirs)?;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn revoke_bridge_extension(
+    state: State<'_, AppState>,
+    extension_id: String,
+) -> Result<(), String> {
+    let mut pairing = state.pairing.lock().map_err(|_| "State poisoned")?;
+    pairing.allowed_extensions.retain(|a| *a != extension_id);
+    pairing.save(&state.dirs)
+}
+
+#[tauri::command]
+pub async fn bridge_status(state: State<'_, AppState>) -> Result<BridgeStatus, String> {
+    let status = state.bridge.lock().map_err(|_| "State poisoned")?;
+    Ok(status.clone())
+}
+
+/// Links from the command line or deep links that arrived before the main
+/// window was listening. Later links are emitted as `adm-add-from-bridge`.
+#[tauri::command]
+pub async fn take_pending_adds(
+    state: State<'_, AppState>,
+) -> Result<Vec<BridgeAddPayload>, String> {
+    let mut pending = state.pending_adds.lock().map_err(|_| "State poisoned")?;
+    Ok(pending.take().unwrap_or_default())
+}
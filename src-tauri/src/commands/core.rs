@@ -1,173 +1,176 @@
+use crate::checksum::{Algorithm, hash_file};
+use crate::engine::conflict::{self, ConflictAnswer};
+use crate::engine::probe;
+use crate::error::DownloadError;
 use crate::payloads::ProbeResult;
+use crate::registry::DownloadRecord;
+use crate::request::RequestOptions;
 use crate::state::AppState;
 use crate::util::guess_category_by_ext;
-use reqwest::header::{CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_RANGE};
 use tauri::State;
 
-fn from_hex(b: u8) -> Option<u8> {
-    match b {
-        b'0'..=b'9' => Some(b - b'0'),
-        b'a'..=b'f' => Some(10 + b - b'a'),
-        b'A'..=b'F' => Some(10 + b - b'A'),
-        _ => None,
-    }
-}
-
-fn percent_decode_simple(s: &str) -> String {
-    let bytes = s.as_bytes();
-    let mut out: Vec<u8> = Vec::with_capacity(bytes.len());
-    let mut i = 0;
-    while i < bytes.len() {
-        if bytes[i] == b'%' && i + 2 < bytes.len() {
-            if let (Some(h), Some(l)) = (from_hex(bytes[i + 1]), from_hex(bytes[i + 2])) {
-                out.push((h << 4) | l);
-                i += 3;
-                continue;
-            }
-        }
-        if bytes[i] == b'+' {
-            out.push(b' ');
-            i += 1;
-            continue;
-        }
-        out.push(bytes[i]);
-        i += 1;
-    }
-    String::from_utf8(out).unwrap_or_else(|_| s.to_string())
-}
-
-fn filename_from_cd(cd: &str) -> Option<String> {
-    let parts: Vec<&str> = cd.split(';').map(|s| s.trim()).collect();
-    let mut filename_star: Option<String> = None;
-    let mut filename: Option<String> = None;
-    for p in parts {
-        if let Some(rest) = p.strip_prefix("filename*=") {
-            let v = rest.trim_matches('"');
-            if let Some(pos) = v.find("''") {
-                let enc = &v[pos + 2..];
-                filename_star = Some(percent_decode_simple(enc));
-            } else {
-                filename_star = Some(percent_decode_simple(v));
-            }
-        } else if let Some(rest) = p.strip_prefix("filename=") {
-            let mut v = rest.trim();
-            if v.starts_with('"') && v.ends_with('"') && v.len() >= 2 {
-                v = &v[1..v.len() - 1];
-            }
-            filename = Some(v.to_string());
-        }
-    }
-    filename_star.or(filename)
-}
-
+/// Inspect `url` without downloading it. Passing the result back as
+/// `resource` lets the download skip its own probe.
 #[tauri::command]
-pub async fn probe_url(url: String) -> Result<ProbeResult, String> {
-    let client = reqwest::Client::new();
-    let head = client
-        .head(&url)
-        .send()
-        .await
-        .map_err(|e| format!("HEAD error: {}", e))?;
-    let mut total = head
-        .headers()
-        .get(CONTENT_LENGTH)
-        .and_then(|v| v.to_str().ok())
-        .and_then(|s| s.parse::<u64>().ok());
-    // filename from Content-Disposition if present; else from URL
-    let mut file_name = if let Some(cd) = head
-        .headers()
-        .get(CONTENT_DISPOSITION)
-        .and_then(|v| v.to_str().ok())
-    {
-        filename_from_cd(cd).unwrap_or_else(|| {
-            let raw = url
-                .split('/')
-                .last()
-                .unwrap_or("download.bin")
-                .split('?')
-                .next()
-                .unwrap_or("download.bin");
-            percent_decode_simple(raw)
-        })
-    } else {
-        let raw = url
-            .split('/')
-            .last()
-            .unwrap_or("download.bin")
-            .split('?')
-            .next()
-            .unwrap_or("download.bin");
-        percent_decode_simple(raw)
-    };
-    if file_name.is_empty() || file_name == "/" {
-        file_name = "download.bin".into();
-    }
-    // If still not good, try a tiny ranged GET to follow redirects and grab headers
-    if (!file_name.contains('.')) || file_name == "download.bin" || total.is_none() {
-        if let Ok(resp) = client
-            .get(&url)
-            .header(reqwest::header::RANGE, "bytes=0-0")
-            .send()
-            .await
-        {
-            if let Some(cd) = resp
-                .headers()
-                .get(CONTENT_DISPOSITION)
-                .and_then(|v| v.to_str().ok())
-            {
-                if let Some(n) = filename_from_cd(cd) {
-                    file_name = n;
-                }
-            }
-            if total.is_none() {
-                if let Some(cr) = resp
-                    .headers()
-                    .get(CONTENT_RANGE)
-                    .and_then(|v| v.to_str().ok())
-                {
-                    if let Some(t) = cr.split('/').nth(1) {
-                        total = t.parse::<u64>().ok();
-                    }
-                }
-            }
-        }
-    }
-    let category = guess_category_by_ext(&file_name);
+pub async fn probe_url(
+    url: String,
+    options: Option<RequestOptions>,
+) -> Result<ProbeResult, DownloadError> {
+    let options = options.unwrap_or_default();
+    let client = options.client()?;
+    let resource = probe::probe(&client, &options, &url).await?;
+    let category = guess_category_by_ext(&resource.file_name);
     let download_dir: String = dirs::download_dir()
-        .or_else(|| dirs::home_dir())
+        .or_else(dirs::home_dir)
         .or_else(|| std::env::current_dir().ok())
         .unwrap_or_else(|| std::path::PathBuf::from("."))
         .to_string_lossy()
         .to_string();
     Ok(ProbeResult {
-        total,
-        file_name,
+        resource,
         category,
         download_dir,
     })
 }
 
+/// Every queued, running and finished download of this session, plus paused
+/// ones left over from earlier sessions, so the UI can rebuild its list.
+#[tauri::command]
+pub async fn list_downloads(state: State<'_, AppState>) -> Result<Vec<DownloadRecord>, String> {
+    crate::registry::list(&state)
+}
+
+/// Stop a download without deleting anything: a queued one leaves the queue,
+/// a running or paused one keeps its `.part` file and sidecar.
 #[tauri::command]
-pub async fn delete_download(state: State<'_, AppState>, id: String) -> Result<(), String> {
-    // cancel if running
-    if let Ok(map) = state.cancels.lock() {
-        if let Some(f) = map.get(&id) {
-            f.store(true, std::sync::atomic::Ordering::Relaxed);
+pub async fn cancel_download(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    id: String,
+) -> Result<(), String> {
+    {
+        let mut queue = state.queue.lock().map_err(|_| "State poisoned")?;
+        if queue.remove(&id).is_some() {
+            queue.save(&state.dirs)?;
+            crate::queue::notify(&app, &queue);
+            return Ok(());
         }
     }
-    // remove files if we have meta
-    if let Ok(mut metas) = state.metas.lock() {
-        if let Some(meta) = metas.remove(&id) {
-            let _ = std::fs::remove_file(&meta.temp);
-            // If a final file exists (rare if incomplete), remove it too
-            let _ = std::fs::remove_file(&meta.dest);
-        }
+    // A download waiting for `resolve_conflict` stops once nobody can answer
+    conflict::dismiss(&state, &id);
+    state
+        .downloads
+        .lock()
+        .map_err(|_| "State poisoned")?
+        .cancel(&id)
+}
+
+/// Answer a `download_conflict` event for the download waiting on it.
+#[tauri::command]
+pub async fn resolve_conflict(
+    state: State<'_, AppState>,
+    id: String,
+    answer: ConflictAnswer,
+) -> Result<(), String> {
+    let waiting = state
+        .conflicts
+        .lock()
+        .map_err(|_| "State poisoned")?
+        .remove(&id)
+        .ok_or_else(|| format!("No pending conflict for {}", id))?;
+    waiting
+        .send(answer)
+        .map_err(|_| "Download is no longer waiting".to_string())
+}
+
+#[tauri::command]
+pub async fn delete_download(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    id: String,
+) -> Result<(), String> {
+    // drop it from the queue if it never started
+    if let Ok(mut queue) = state.queue.lock()
+        && queue.remove(&id).is_some()
+    {
+        let _ = queue.save(&state.dirs);
+        crate::queue::notify(&app, &queue);
+    }
+    // cancel if running, and remove the files of an unfinished download; a
+    // running transfer stops saving its sidecar once the entry is gone and
+    // removes any file it still held open when it stops
+    conflict::dismiss(&state, &id);
+    let partial = state
+        .downloads
+        .lock()
+        .map_err(|_| "State poisoned")?
+        .remove(&id);
+    if let Some(meta) = partial {
+        let _ = std::fs::remove_file(&meta.temp);
+        // The final name is only ours while it is still the empty placeholder;
+        // with Overwrite it is the user's file
+        conflict::release(&meta.dest);
     }
-    // remove cancel flag entry
+    // forget any persisted resume data
+    crate::sidecar::remove(&state.dirs, &id);
     let _ = state
-        .cancels
+        .limiters
         .lock()
         .map_err(|_| "State poisoned")?
         .remove(&id);
     Ok(())
 }
+
+/// Limit throughput in bytes per second (0 = unlimited) for one download, or
+/// for all downloads combined when `target` is `"global"`. Applies immediately
+/// to running transfers.
+#[tauri::command]
+pub async fn set_speed_limit(
+    state: State<'_, AppState>,
+    target: String,
+    bytes_per_sec: u64,
+) -> Result<(), String> {
+    if target == "global" {
+        let mut settings = state.settings.lock().map_err(|_| "State poisoned")?;
+        settings.global_speed_limit = bytes_per_sec;
+        crate::settings::save(&state.dirs, &settings)?;
+        state.global_limiter.set_rate(bytes_per_sec);
+    } else {
+        let queued = state
+            .queue
+            .lock()
+            .map_err(|_| "State poisoned")?
+            .items
+            .iter()
+            .any(|item| item.id == target);
+        if !queued
+            && !state
+                .downloads
+                .lock()
+                .map_err(|_| "State poisoned")?
+                .contains(&target)
+        {
+            return Err("Unknown download".into());
+        }
+        state.limiter_for(&target).set_rate(bytes_per_sec);
+    }
+    Ok(())
+}
+
+/// Hash a file on disk, reporting `download_verifying` progress keyed by its path.
+/// Returns the lowercase hex digest.
+#[tauri::command]
+pub async fn verify_file(
+    app: tauri::AppHandle,
+    path: String,
+    algo: String,
+) -> Result<String, DownloadError> {
+    let algorithm = Algorithm::parse(&algo)?;
+    hash_file(
+        &super::events::app_events(&app),
+        &path,
+        std::path::Path::new(&path),
+        algorithm,
+    )
+    .await
+}
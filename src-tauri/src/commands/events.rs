@@ -0,0 +1,54 @@
+use std::sync::Arc;
+
+use tauri::Emitter;
+
+use crate::engine::events::{DownloadEvents, Events};
+use crate::payloads::{
+    CanceledPayload, CompletedPayload, ConflictPayload, FailedPayload, PausedPayload,
+    ProgressPayload, ResumedPayload, StartedPayload, VerifyingPayload,
+};
+
+/// The app's receiver of engine events: each one goes to the webviews as a
+/// `download_*` event.
+impl DownloadEvents for tauri::AppHandle {
+    fn started(&self, payload: &StartedPayload) {
+        let _ = self.emit("download_started", payload);
+    }
+
+    fn resumed(&self, payload: &ResumedPayload) {
+        let _ = self.emit("download_resumed", payload);
+    }
+
+    fn progress(&self, payload: &ProgressPayload) {
+        let _ = self.emit("download_progress", payload);
+    }
+
+    fn paused(&self, payload: &PausedPayload) {
+        let _ = self.emit("download_paused", payload);
+    }
+
+    fn verifying(&self, payload: &VerifyingPayload) {
+        let _ = self.emit("download_verifying", payload);
+    }
+
+    fn completed(&self, payload: &CompletedPayload) {
+        let _ = self.emit("download_completed", payload);
+    }
+
+    fn failed(&self, payload: &FailedPayload) {
+        let _ = self.emit("download_failed", payload);
+    }
+
+    fn canceled(&self, payload: &CanceledPayload) {
+        let _ = self.emit("download_canceled", payload);
+    }
+
+    fn conflict(&self, payload: &ConflictPayload) -> bool {
+        self.emit("download_conflict", payload).is_ok()
+    }
+}
+
+/// Events for Tauri commands that drive the engine.
+pub fn app_events(app: &tauri::AppHandle) -> Events {
+    Arc::new(app.clone())
+}
@@ -1,3 +1,7 @@
+pub mod bridge;
 pub mod core;
+pub mod events;
 pub mod http;
 pub mod manic;
+pub mod queue;
+pub mod settings;
@@ -0,0 +1,130 @@
+use tauri::State;
+
+use crate::engine::probe::ResourceInfo;
+use crate::payloads::{BatchResult, DownloadRequest};
+use crate::queue::{self, Engine, QueueItem};
+use crate::request::RequestOptions;
+use crate::state::AppState;
+use crate::util::new_download_id;
+
+/// Add a download to the queue and return its id; it starts once a slot is free.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn enqueue_download(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    url: String,
+    threads: u8,
+    dest_dir: Option<String>,
+    file_name: Option<String>,
+    expected_checksum: Option<String>,
+    engine: Option<Engine>,
+    priority: Option<i32>,
+    options: Option<RequestOptions>,
+    resource: Option<ResourceInfo>,
+) -> Result<String, String> {
+    let id = new_download_id();
+    {
+        let mut queue = state.queue.lock().map_err(|_| "State poisoned")?;
+        queue.push(QueueItem {
+            id: id.clone(),
+            request: DownloadRequest {
+                url,
+                threads,
+                dest_dir,
+                file_name,
+                expected_checksum,
+                options: options.unwrap_or_default(),
+                resource,
+            },
+            engine: engine.unwrap_or(Engine::Http),
+            priority: priority.unwrap_or(0),
+        });
+        queue.save(&state.dirs)?;
+        queue::notify(&app, &queue);
+    }
+    queue::pump(&app);
+    Ok(id)
+}
+
+#[tauri::command]
+pub async fn list_queue(state: State<'_, AppState>) -> Result<Vec<QueueItem>, String> {
+    let queue = state.queue.lock().map_err(|_| "State poisoned")?;
+    Ok(queue.items.clone())
+}
+
+#[tauri::command]
+pub async fn reorder_queue(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    id: String,
+    position: usize,
+) -> Result<(), String> {
+    let mut queue = state.queue.lock().map_err(|_| "State poisoned")?;
+    queue.reorder(&id, position)?;
+    queue.save(&state.dirs)?;
+    queue::notify(&app, &queue);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn move_to_top(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    id: String,
+) -> Result<(), String> {
+    let mut queue = state.queue.lock().map_err(|_| "State poisoned")?;
+    queue.reorder(&id, 0)?;
+    queue.save(&state.dirs)?;
+    queue::notify(&app, &queue);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn remove_from_queue(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    id: String,
+) -> Result<(), String> {
+    let mut queue = state.queue.lock().map_err(|_| "State poisoned")?;
+    queue
+        .remove(&id)
+        .ok_or_else(|| format!("No queued download with id {}", id))?;
+    queue.save(&state.dirs)?;
+    queue::notify(&app, &queue);
+    Ok(())
+}
+
+/// Queue every http(s) link in `text` (e.g. a clipboard dump) or in the text
+/// file at `path`, skipping links that already have a download.
+#[tauri::command]
+pub async fn import_links(
+    app: tauri::AppHandle,
+    text: Option<String>,
+    path: Option<String>,
+    dest_dir: Option<String>,
+    category: Option<String>,
+    threads: Option<u8>,
+) -> Result<BatchResult, String> {
+    let text = match (text, path) {
+        (Some(text), _) => text,
+        (None, Some(path)) => {
+            std::fs::read_to_string(&path).map_err(|e| format!("Read error: {}", e))?
+        }
+        (None, None) => return Err("Nothing to import".into()),
+    };
+    let dest_dir = queue::batch_dest_dir(dest_dir, category);
+    let requests = crate::util::extract_links(&text)
+        .into_iter()
+        .map(|url| DownloadRequest {
+            url,
+            threads: threads.unwrap_or(queue::DEFAULT_THREADS),
+            dest_dir: dest_dir.clone(),
+            file_name: None,
+            expected_checksum: None,
+            options: Default::default(),
+            resource: None,
+        })
+        .collect();
+    queue::enqueue_batch(&app, requests)
+}
@@ -0,0 +1,24 @@
+use tauri::State;
+
+use crate::settings::{self, Settings};
+use crate::state::AppState;
+
+#[tauri::command]
+pub async fn get_settings(state: State<'_, AppState>) -> Result<Settings, String> {
+    let settings = state.settings.lock().map_err(|_| "State poisoned")?;
+    Ok(settings.clone())
+}
+
+#[tauri::command]
+pub async fn update_settings(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    settings: Settings,
+) -> Result<(), String> {
+    settings::save(&state.dirs, &settings)?;
+    state.global_limiter.set_rate(settings.global_speed_limit);
+    *state.settings.lock().map_err(|_| "State poisoned")? = settings;
+    // A higher limit may free slots for queued downloads
+    crate::queue::pump(&app);
+    Ok(())
+}
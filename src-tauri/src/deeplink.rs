@@ -0,0 +1,65 @@
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::payloads::BridgeAddPayload;
+use crate::state::AppState;
+
+/// `adm://add?url=...&name=...&dir=...` as an add request; `None` for any
+/// other link or a missing `url`.
+pub fn parse_deep_link(link: &str) -> Option<BridgeAddPayload> {
+    let parsed = url::Url::parse(link).ok()?;
+    if parsed.scheme() != "adm" || parsed.host_str() != Some("add") {
+        return None;
+    }
+    let mut payload = BridgeAddPayload {
+        url: String::new(),
+        file_name: None,
+        dest_dir: None,
+        options: Default::default(),
+    };
+    for (key, value) in parsed.query_pairs() {
+        let value = value.trim().to_string();
+        if value.is_empty() {
+            continue;
+        }
+        match key.as_ref() {
+            "url" => payload.url = value,
+            "name" => payload.file_name = Some(value),
+            "dir" => payload.dest_dir = Some(value),
+            _ => {}
+        }
+    }
+    is_download_url(&payload.url).then_some(payload)
+}
+
+/// Plain http(s) URLs among command-line arguments. `adm://` links are left to
+/// the deep-link plugin, which also receives them from second instances.
+pub fn urls_from_args(args: &[String]) -> Vec<BridgeAddPayload> {
+    args.iter()
+        .skip(1)
+        .filter(|arg| is_download_url(arg))
+        .map(|arg| BridgeAddPayload {
+            url: arg.clone(),
+            file_name: None,
+            dest_dir: None,
+            options: Default::default(),
+        })
+        .collect()
+}
+
+fn is_download_url(s: &str) -> bool {
+    url::Url::parse(s).is_ok_and(|u| matches!(u.scheme(), "http" | "https"))
+}
+
+/// Hand a link to the add window through the same event the bridge uses. Until
+/// the main window has asked for pending links, they are held back so links
+/// from a cold start aren't emitted before anyone listens.
+pub fn dispatch(app: &AppHandle, payload: BridgeAddPayload) {
+    let state = app.state::<AppState>();
+    if let Ok(mut pending) = state.pending_adds.lock()
+        && let Some(pending) = pending.as_mut()
+    {
+        pending.push(payload);
+        return;
+    }
+    let _ = app.emit("adm-add-from-bridge", payload);
+}
@@ -0,0 +1,262 @@
+//! What a download does when its file name is already taken, and the
+//! per-download `.part` names that keep simultaneous downloads apart.
+
+use std::fs::OpenOptions;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+
+use crate::checksum::{self, ExpectedChecksum};
+use crate::error::{DownloadError, ErrorKind};
+use crate::payloads::{CompletedPayload, ConflictPayload, StartedPayload};
+use crate::registry::DownloadFlags;
+use crate::state::{AppState, DownloadMeta};
+use crate::util::split_dest;
+
+use super::events::Events;
+
+/// How long a `download_conflict` question waits for an answer before the
+/// download falls back to renaming. An emitted event doesn't mean anyone
+/// is listening for it.
+const ASK_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// How a download handles an existing file with the same name; set in
+/// [`crate::settings::Settings`].
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictPolicy {
+    /// Save as `name (1).ext`, `name (2).ext`...
+    #[default]
+    Rename,
+    Overwrite,
+    /// Keep the existing file when it has the expected size (and checksum,
+    /// if one was given) instead of downloading it again; rename otherwise.
+    SkipIdentical,
+    /// Emit `download_conflict` and wait for `resolve_conflict`; renames when
+    /// no answer comes.
+    Ask,
+}
+
+/// The answer to a `download_conflict` event.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictAnswer {
+    Rename,
+    Overwrite,
+    /// Keep the existing file as the result.
+    Skip,
+    Cancel,
+}
+
+/// Where a download ends up once any conflict is settled.
+pub enum Target {
+    /// Download into this path. Unless it replaces an existing file, an empty
+    /// file already holds the name so no other download can take it.
+    Write(PathBuf),
+    /// The file already there is the result; nothing needs downloading.
+    Existing(PathBuf),
+}
+
+/// Claim `dest` for download `id`, applying the conflict policy when the
+/// name is taken.
+pub async fn resolve(
+    events: &Events,
+    state: &AppState,
+    id: &str,
+    dest: PathBuf,
+    total: Option<u64>,
+    expected: Option<&ExpectedChecksum>,
+) -> Result<Target, DownloadError> {
+    if reserve(&dest)? {
+        return Ok(Target::Write(dest));
+    }
+    let policy = state
+        .settings
+        .lock()
+        .map_err(|_| "State poisoned")?
+        .conflict_policy;
+    let answer = match policy {
+        ConflictPolicy::Rename => ConflictAnswer::Rename,
+        ConflictPolicy::Overwrite => ConflictAnswer::Overwrite,
+        ConflictPolicy::SkipIdentical => {
+            if is_identical(events, id, &dest, total, expected).await? {
+                ConflictAnswer::Skip
+            } else {
+                ConflictAnswer::Rename
+            }
+        }
+        ConflictPolicy::Ask => ask(events, state, id, &dest, total).await?,
+    };
+    match answer {
+        ConflictAnswer::Rename => Ok(Target::Write(reserve_numbered(&dest)?)),
+        ConflictAnswer::Overwrite => Ok(Target::Write(dest)),
+        ConflictAnswer::Skip => Ok(Target::Existing(dest)),
+        ConflictAnswer::Cancel => Err(DownloadError::canceled()),
+    }
+}
+
+/// Report the file already at `dest` as the finished download.
+pub fn keep_existing(
+    events: &Events,
+    state: &AppState,
+    id: &str,
+    url: &str,
+    dest: PathBuf,
+    flags: &DownloadFlags,
+) -> Result<String, DownloadError> {
+    let size = std::fs::metadata(&dest)
+        .map_err(|e| DownloadError::io("Read file", e))?
+        .len();
+    flags.received.store(size, Ordering::Relaxed);
+    let path = dest.to_string_lossy().to_string();
+    let (file_name, dest_dir) = split_dest(&dest);
+    events.started(&StartedPayload {
+        id: id.to_string(),
+        url: url.to_string(),
+        file_name,
+        dest_dir,
+        total: Some(size),
+    });
+    state
+        .downloads
+        .lock()
+        .map_err(|_| "State poisoned")?
+        .downloading(
+            id,
+            DownloadMeta {
+                url: url.to_string(),
+                temp: temp_path(&dest, id),
+                dest,
+                total: Some(size),
+                accept_ranges: false,
+                etag: None,
+                last_modified: None,
+            },
+        )?;
+    events.completed(&CompletedPayload {
+        id: id.to_string(),
+        path: path.clone(),
+    });
+    Ok(path)
+}
+
+/// Give up a name reserved by [`resolve`]: removes the file at `dest` if it
+/// is still the empty placeholder.
+pub fn release(dest: &Path) {
+    if std::fs::metadata(dest).is_ok_and(|m| m.is_file() && m.len() == 0) {
+        let _ = std::fs::remove_file(dest);
+    }
+}
+
+/// Drop a pending `download_conflict` question, which cancels the download
+/// waiting for the answer.
+pub fn dismiss(state: &AppState, id: &str) {
+    if let Ok(mut conflicts) = state.conflicts.lock() {
+        conflicts.remove(id);
+    }
+}
+
+/// The `.part` file download `id` writes to before it is renamed to `dest`.
+/// The id keeps downloads of the same name from sharing one.
+pub fn temp_path(dest: &Path, id: &str) -> PathBuf {
+    let name = dest
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    dest.with_file_name(format!("{}.{}.part", name, id))
+}
+
+async fn ask(
+    events: &Events,
+    state: &AppState,
+    id: &str,
+    dest: &Path,
+    total: Option<u64>,
+) -> Result<ConflictAnswer, DownloadError> {
+    let (tx, rx) = oneshot::channel();
+    state
+        .conflicts
+        .lock()
+        .map_err(|_| "State poisoned")?
+        .insert(id.to_string(), tx);
+    let payload = ConflictPayload {
+        id: id.to_string(),
+        path: dest.to_string_lossy().to_string(),
+        size: std::fs::metadata(dest).map(|m| m.len()).unwrap_or(0),
+        total,
+    };
+    if !events.conflict(&payload) {
+        // Nobody to ask, e.g. the CLI
+        dismiss(state, id);
+        return Ok(ConflictAnswer::Rename);
+    }
+    match tokio::time::timeout(ASK_TIMEOUT, rx).await {
+        Ok(answer) => {
+            // A dropped sender means the download was canceled while waiting
+            Ok(answer.unwrap_or(ConflictAnswer::Cancel))
+        }
+        Err(_) => {
+            dismiss(state, id);
+            Ok(ConflictAnswer::Rename)
+        }
+    }
+}
+
+async fn is_identical(
+    events: &Events,
+    id: &str,
+    dest: &Path,
+    total: Option<u64>,
+    expected: Option<&ExpectedChecksum>,
+) -> Result<bool, DownloadError> {
+    let size = std::fs::metadata(dest).map(|m| m.len()).ok();
+    if total.is_none() || size != total {
+        return Ok(false);
+    }
+    match expected {
+        Some(expected) => {
+            let actual = checksum::hash_file(events, id, dest, expected.algorithm).await?;
+            Ok(actual == expected.hex)
+        }
+        None => Ok(true),
+    }
+}
+
+/// Create `path` as an empty file unless something already has that name.
+fn reserve(path: &Path) -> Result<bool, DownloadError> {
+    match OpenOptions::new().write(true).create_new(true).open(path) {
+        Ok(_) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Ok(false),
+        Err(e) => Err(DownloadError::io("Create file", e)),
+    }
+}
+
+/// Reserve the first free `name (n).ext` next to `dest`.
+fn reserve_numbered(dest: &Path) -> Result<PathBuf, DownloadError> {
+    let name = dest
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    // Keep double extensions like `.tar.gz` together
+    let split = name
+        .to_ascii_lowercase()
+        .find(".tar.")
+        .filter(|&i| i > 0)
+        .or_else(|| name.rfind('.').filter(|&i| i > 0))
+        .unwrap_or(name.len());
+    let (stem, extension) = name.split_at(split);
+    for n in 1..10_000 {
+        let candidate = dest.with_file_name(format!("{} ({}){}", stem, n, extension));
+        if reserve(&candidate)? {
+            return Ok(candidate);
+        }
+    }
+    Err(DownloadError::new(
+        ErrorKind::Io,
+        format!("No free file name left for {}", name),
+    ))
+}
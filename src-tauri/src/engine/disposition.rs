@@ -0,0 +1,304 @@
+//! `Content-Disposition` parsing (RFC 6266) with the `filename*` extended
+//! notation of RFC 8187 and the encodings servers use in practice.
+//!
+//! Works on the raw header bytes: servers on Chinese and Japanese sites often
+//! put GBK or Shift_JIS bytes straight into `filename="..."`, which a `&str`
+//! view of the header would reject. Nothing here panics on malformed input.
+
+use std::borrow::Cow;
+
+use encoding_rs::{Encoding, GBK, SHIFT_JIS, UTF_8, WINDOWS_1252};
+
+/// Legacy encodings tried, in order, for a raw `filename` that isn't UTF-8.
+/// `WINDOWS_1252` (what ISO-8859-1 means on the web) decodes anything, so it
+/// comes last.
+const FALLBACKS: [&Encoding; 2] = [GBK, SHIFT_JIS];
+
+/// The file name a `Content-Disposition` value asks for: `filename*` when it
+/// decodes, else `filename`. `charset` is a hint for raw non-UTF-8 bytes, e.g.
+/// the `charset` parameter of the response's `Content-Type`.
+pub fn filename(value: &[u8], charset: Option<&str>) -> Option<String> {
+    let params = params(value);
+    let get = |wanted: &str| {
+        params
+            .iter()
+            .find(|(name, _)| name == wanted)
+            .map(|(_, value)| value.as_slice())
+    };
+    get("filename*")
+        .and_then(ext_value)
+        .or_else(|| get("filename").and_then(|raw| plain_value(raw, charset)))
+        .filter(|name| !name.trim().is_empty())
+}
+
+/// `name=value` pairs after the disposition type, names lowercased and
+/// quoted-strings unescaped. The first occurrence of a name wins. A missing
+/// disposition type (`filename="a.zip"` on its own) is tolerated.
+fn params(value: &[u8]) -> Vec<(String, Vec<u8>)> {
+    let mut out: Vec<(String, Vec<u8>)> = Vec::new();
+    let mut i = 0;
+    // Skip the disposition type unless the first segment is already a parameter
+    let first_end = value.iter().position(|&b| b == b';').unwrap_or(value.len());
+    if !value[..first_end].contains(&b'=') {
+        i = first_end;
+    }
+    while i < value.len() {
+        if value[i] == b';' {
+            i += 1;
+        }
+        let name_start = i;
+        while i < value.len() && value[i] != b'=' && value[i] != b';' {
+            i += 1;
+        }
+        let name = String::from_utf8_lossy(value[name_start..i].trim_ascii()).to_ascii_lowercase();
+        if i >= value.len() || value[i] == b';' {
+            // A parameter without a value
+            continue;
+        }
+        i += 1;
+        while i < value.len() && value[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        let mut raw = Vec::new();
+        if i < value.len() && value[i] == b'"' {
+            i += 1;
+            while i < value.len() {
+                match value[i] {
+                    b'\\' if i + 1 < value.len() => {
+                        raw.push(value[i + 1]);
+                        i += 2;
+                    }
+                    b'"' => {
+                        i += 1;
+                        break;
+                    }
+                    b => {
+                        raw.push(b);
+                        i += 1;
+                    }
+                }
+            }
+            // Anything between the closing quote and the next `;` is junk
+            while i < value.len() && value[i] != b';' {
+                i += 1;
+            }
+        } else {
+            let start = i;
+            while i < value.len() && value[i] != b';' {
+                i += 1;
+            }
+            raw.extend_from_slice(value[start..i].trim_ascii());
+        }
+        if !name.is_empty() && !out.iter().any(|(n, _)| *n == name) {
+            out.push((name, raw));
+        }
+    }
+    out
+}
+
+/// RFC 8187 `charset'language'percent-encoded`, e.g. `UTF-8''%E4%B8%AD.zip`
+/// or `GBK''%D6%D0.zip`. Unknown charsets are read as UTF-8.
+fn ext_value(raw: &[u8]) -> Option<String> {
+    let raw = raw.trim_ascii();
+    let mut parts = raw.splitn(3, |&b| b == b'\'');
+    let (charset, encoded) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(charset), Some(_language), Some(encoded)) => (charset, encoded),
+        // Not an ext-value; take it as percent-encoded UTF-8
+        _ => (&b"utf-8"[..], raw),
+    };
+    let encoding = Encoding::for_label(charset.trim_ascii()).unwrap_or(UTF_8);
+    decode(encoding, &percent_decode_bytes(encoded)).map(Cow::into_owned)
+}
+
+/// A plain `filename`: ASCII as is, or percent-decoded when that yields UTF-8
+/// (a common non-standard way to send non-ASCII names); raw bytes as UTF-8,
+/// the `charset` hint, or the best-looking legacy encoding.
+fn plain_value(raw: &[u8], charset: Option<&str>) -> Option<String> {
+    if raw.is_ascii() {
+        let decoded = percent_decode_bytes(raw);
+        if !decoded.is_ascii()
+            && let Ok(name) = String::from_utf8(decoded)
+        {
+            return Some(name);
+        }
+        return Some(String::from_utf8_lossy(raw).into_owned());
+    }
+    if let Some(encoding) = charset.and_then(|c| Encoding::for_label(c.trim().as_bytes()))
+        && let Some(name) = decode(encoding, raw)
+    {
+        return Some(name.into_owned());
+    }
+    if let Ok(name) = std::str::from_utf8(raw) {
+        return Some(name.to_string());
+    }
+    FALLBACKS
+        .iter()
+        .filter_map(|&encoding| decode(encoding, raw))
+        .max_by_key(|name| plausibility(name))
+        .or_else(|| decode(WINDOWS_1252, raw))
+        .map(Cow::into_owned)
+}
+
+fn decode<'a>(encoding: &'static Encoding, bytes: &'a [u8]) -> Option<Cow<'a, str>> {
+    encoding.decode_without_bom_handling_and_without_replacement(bytes)
+}
+
+/// How much `name` looks like real text rather than a misread encoding. GBK
+/// read as Shift_JIS turns into half-width katakana, which names hardly use,
+/// while real Shift_JIS names usually contain kana. Ties go to the earlier
+/// encoding in `FALLBACKS`.
+fn plausibility(name: &str) -> i64 {
+    name.chars()
+        .map(|c| match c {
+            '\u{3040}'..='\u{30ff}' => 2,
+            '\u{ff61}'..='\u{ff9f}' | '\u{e000}'..='\u{f8ff}' => -3,
+            c if c.is_control() => -3,
+            _ => 0,
+        })
+        .sum()
+}
+
+fn from_hex(b: u8) -> Option<u8> {
+    match b {
+        b'0'..=b'9' => Some(b - b'0'),
+        b'a'..=b'f' => Some(10 + b - b'a'),
+        b'A'..=b'F' => Some(10 + b - b'A'),
+        _ => None,
+    }
+}
+
+/// `%XX` escapes to bytes; anything else, including malformed escapes, is
+/// kept as is.
+pub fn percent_decode_bytes(bytes: &[u8]) -> Vec<u8> {
+    let mut out: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && i + 2 < bytes.len()
+            && let (Some(h), Some(l)) = (from_hex(bytes[i + 1]), from_hex(bytes[i + 2]))
+        {
+            out.push((h << 4) | l);
+            i += 3;
+            continue;
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_the_names_servers_send() {
+        let cases: &[(&[u8], Option<&str>, Option<&str>)] = &[
+            (b"attachment; filename=\"a;b.zip\"", None, Some("a;b.zip")),
+            (
+                b"attachment; filename=\"a\\\"b\\\\c.zip\"",
+                None,
+                Some("a\"b\\c.zip"),
+            ),
+            (
+                b"attachment; filename=\"plain.zip\"; filename*=UTF-8''%E4%B8%AD%E6%96%87.zip",
+                None,
+                Some("中文.zip"),
+            ),
+            (
+                b"attachment; filename*=UTF-8''%E4%B8%AD.zip; filename=\"plain.zip\"",
+                None,
+                Some("中.zip"),
+            ),
+            // A `filename*` that doesn't decode falls back to `filename`
+            (
+                b"attachment; filename=\"fallback.zip\"; filename*=UTF-8''%FF.zip",
+                None,
+                Some("fallback.zip"),
+            ),
+            (
+                b"attachment; filename*=iso-8859-1'en'%A3%20rates.txt",
+                None,
+                Some("£ rates.txt"),
+            ),
+            (
+                b"attachment; filename*=GBK''%D6%D0%CE%C4.zip",
+                None,
+                Some("中文.zip"),
+            ),
+            (
+                b"attachment; filename*=Shift_JIS''%83%65%83%58%83%67.txt",
+                None,
+                Some("テスト.txt"),
+            ),
+            (
+                b"attachment; filename=\"\xd6\xd0\xce\xc4.zip\"",
+                None,
+                Some("中文.zip"),
+            ),
+            (
+                b"attachment; filename=\"\xd6\xd0\xce\xc4.zip\"",
+                Some("gbk"),
+                Some("中文.zip"),
+            ),
+            // The hint wins over the guess
+            (
+                b"attachment; filename=\"\xd6\xd0\xce\xc4.zip\"",
+                Some("shift_jis"),
+                Some("ﾖﾐﾎﾄ.zip"),
+            ),
+            (
+                b"attachment; filename=\"\x83\x65\x83\x58\x83\x67.txt\"",
+                None,
+                Some("テスト.txt"),
+            ),
+            (
+                b"attachment; filename=\"\x83\x65\x83\x58\x83\x67.txt\"",
+                Some("Shift_JIS"),
+                Some("テスト.txt"),
+            ),
+            (
+                b"attachment; filename=\"%E4%B8%AD.zip\"",
+                None,
+                Some("中.zip"),
+            ),
+            (
+                b"attachment; filename=\"unterminated.zip",
+                None,
+                Some("unterminated.zip"),
+            ),
+            (b"attachment; filename*=UTF-8''name%", None, Some("name%")),
+            (b"attachment; filename*=UTF-8''name%4", None, Some("name%4")),
+            (b"attachment; filename=\"name\\", None, Some("name\\")),
+            (b"filename=\"a.zip\"", None, Some("a.zip")),
+            (b"ATTACHMENT; FileName=\"A.zip\"", None, Some("A.zip")),
+            (b"attachment; filename=\"\"", None, None),
+            (b"attachment", None, None),
+        ];
+        for &(value, charset, expected) in cases {
+            assert_eq!(
+                filename(value, charset).as_deref(),
+                expected,
+                "{:?} with charset {:?}",
+                String::from_utf8_lossy(value),
+                charset,
+            );
+        }
+    }
+
+    /// Every file of the corpus (laid out for cargo-fuzz), cut at every byte.
+    #[test]
+    fn never_panics_on_the_corpus() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/fuzz/corpus/disposition");
+        let entries = std::fs::read_dir(dir).expect("read corpus");
+        for entry in entries.flatten() {
+            let value = std::fs::read(entry.path()).expect("read corpus file");
+            for end in 0..=value.len() {
+                for charset in [None, Some("gbk"), Some("shift_jis"), Some("bogus")] {
+                    let _ = filename(&value[..end], charset);
+                    let _ = filename(&value[end..], charset);
+                }
+            }
+        }
+    }
+}
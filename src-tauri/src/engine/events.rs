@@ -0,0 +1,27 @@
+use std::sync::Arc;
+
+use crate::payloads::{
+    CanceledPayload, CompletedPayload, ConflictPayload, FailedPayload, PausedPayload,
+    ProgressPayload, ResumedPayload, StartedPayload, VerifyingPayload,
+};
+
+/// Receives the engine's download events. The app forwards them to its
+/// webviews as `download_*` events; the CLI renders them in the terminal.
+/// Every method defaults to doing nothing.
+pub trait DownloadEvents: Send + Sync {
+    fn started(&self, _payload: &StartedPayload) {}
+    fn resumed(&self, _payload: &ResumedPayload) {}
+    fn progress(&self, _payload: &ProgressPayload) {}
+    fn paused(&self, _payload: &PausedPayload) {}
+    fn verifying(&self, _payload: &VerifyingPayload) {}
+    fn completed(&self, _payload: &CompletedPayload) {}
+    fn failed(&self, _payload: &FailedPayload) {}
+    fn canceled(&self, _payload: &CanceledPayload) {}
+    /// Ask what to do about an existing file; returns whether anyone was asked.
+    /// The answer comes back through `resolve_conflict`.
+    fn conflict(&self, _payload: &ConflictPayload) -> bool {
+        false
+    }
+}
+
+pub type Events = Arc<dyn DownloadEvents>;
@@ -0,0 +1,1154 @@
+use std::{
+    fs::OpenOptions,
+    io::{Seek, SeekFrom, Write},
+    path::PathBuf,
+    sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+    sync::{Arc, Mutex},
+};
+
+use futures_util::StreamExt;
+use reqwest::StatusCode;
+use reqwest::header::{CONTENT_RANGE, ETAG, HeaderMap, IF_RANGE, LAST_MODIFIED, RANGE};
+use tokio::time::{self, Duration, Instant};
+
+use crate::checksum::{self, ExpectedChecksum};
+use crate::error::{DownloadError, ErrorKind};
+use crate::filename;
+use crate::limiter::Throttle;
+use crate::payloads::{
+    CanceledPayload, CompletedPayload, DownloadRequest, PausedPayload, ProgressPayload,
+    ResumedPayload, StartedPayload,
+};
+use crate::registry::DownloadFlags;
+use crate::request::RequestOptions;
+use crate::retry::{RetryPolicy, SegmentFailure};
+use crate::sidecar::{self, SegmentRecord, Sidecar};
+use crate::state::{AppState, DownloadMeta};
+use crate::util::{new_download_id, split_dest};
+
+use super::conflict::{self, Target};
+use super::events::Events;
+use super::probe::{self, header_string};
+use super::settle;
+
+const SIDECAR_SAVE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Probe and download `url`; `id` is pre-assigned when the download comes from the queue.
+pub async fn run_download(
+    events: &Events,
+    state: &AppState,
+    id: Option<String>,
+    request: DownloadRequest,
+) -> Result<String, DownloadError> {
+    let id = id.unwrap_or_else(new_download_id);
+    let flags = state
+        .downloads
+        .lock()
+        .map_err(|_| "State poisoned")?
+        .begin(&id, &request.url, true)?;
+    let res = download(events, state, id.clone(), flags, request).await;
+    settle(events, state, &id, &res);
+    res
+}
+
+async fn download(
+    events: &Events,
+    state: &AppState,
+    id: String,
+    flags: DownloadFlags,
+    request: DownloadRequest,
+) -> Result<String, DownloadError> {
+    let DownloadRequest {
+        url,
+        threads,
+        dest_dir,
+        file_name,
+        expected_checksum,
+        options,
+        resource,
+    } = request;
+    // Reject a malformed checksum before any bytes are transferred
+    let expected = expected_checksum
+        .as_deref()
+        .map(ExpectedChecksum::parse)
+        .transpose()?;
+    let threads = threads.clamp(1, 32) as u64;
+    let client = options.client()?;
+
+    // A fresh probe from `probe_url` saves the round trips
+    let info = match resource.filter(|info| info.is_fresh_for(&url)) {
+        Some(info) => info,
+        None => probe::probe(&client, &options, &url).await?,
+    };
+    let decided_name = filename::sanitize(file_name.as_deref().unwrap_or(&info.file_name));
+    let len_opt = info.total;
+    let accept_ranges = info.accept_ranges;
+    let etag = info.etag;
+    let last_modified = info.last_modified;
+
+    let mut base_dir: PathBuf = if let Some(custom) = dest_dir {
+        PathBuf::from(custom)
+    } else {
+        dirs::download_dir()
+            .or_else(dirs::home_dir)
+            .or_else(|| std::env::current_dir().ok())
+            .ok_or_else(|| "Cannot resolve a writable directory".to_string())?
+    };
+    std::fs::create_dir_all(&base_dir).map_err(|e| DownloadError::io("Create dir", e))?;
+    base_dir.push(decided_name);
+    let dest =
+        match conflict::resolve(events, state, &id, base_dir, len_opt, expected.as_ref()).await? {
+            Target::Write(dest) => dest,
+            Target::Existing(dest) => {
+                return conflict::keep_existing(events, state, &id, &url, dest, &flags);
+            }
+        };
+    let temp = conflict::temp_path(&dest, &id);
+
+    let mut sidecar = Sidecar {
+        id: id.clone(),
+        url: url.clone(),
+        dest,
+        temp,
+        total: len_opt,
+        accept_ranges,
+        etag,
+        last_modified,
+        connections: threads as usize,
+        expected_checksum,
+        options,
+        segments: Vec::new(),
+    };
+    events.started(&started_payload(&sidecar));
+    let controls = register(state, &sidecar, flags)?;
+
+    if len_opt.is_none() || !accept_ranges || threads == 1 {
+        if let Some(total) = len_opt.filter(|t| *t > 0) {
+            sidecar.segments.push(SegmentRecord {
+                start: 0,
+                end: total - 1,
+                done: 0,
+            });
+        }
+        return download_single(events, state, &client, sidecar, controls).await;
+    }
+
+    let total = len_opt.ok_or_else(|| "Server didn't provide content length".to_string())?;
+    if total == 0 {
+        let _ = std::fs::remove_file(&sidecar.temp);
+        return Err("empty content (total size is 0)".into());
+    }
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(&sidecar.temp)
+        .map_err(|e| DownloadError::io("Open file", e))?;
+    file.set_len(total)
+        .map_err(|e| DownloadError::io("Pre-allocate", e))?;
+    drop(file);
+
+    let chunk_size = total.div_ceil(threads);
+    for i in 0..threads {
+        let start = i * chunk_size;
+        if start >= total {
+            break;
+        }
+        let end = (start + chunk_size - 1).min(total - 1);
+        sidecar.segments.push(SegmentRecord {
+            start,
+            end,
+            done: 0,
+        });
+    }
+    checkpoint(state, &sidecar)?;
+    download_segments(events, state, &client, sidecar, controls).await
+}
+
+/// Ask a running download to stop at the next chunk, keeping its `.part` file
+/// and sidecar for `run_resume`.
+pub fn pause(state: &AppState, id: &str) -> Result<(), String> {
+    state
+        .downloads
+        .lock()
+        .map_err(|_| "State poisoned")?
+        .pause(id)
+}
+
+/// Continue the download persisted in sidecar `id`, fetching only the byte
+/// ranges that are still missing from its `.part` file.
+pub async fn run_resume(
+    events: &Events,
+    state: &AppState,
+    id: String,
+) -> Result<String, DownloadError> {
+    let sidecar = sidecar::load(&state.dirs, &id)?;
+    let flags = state
+        .downloads
+        .lock()
+        .map_err(|_| "State poisoned")?
+        .begin(&id, &sidecar.url, true)?;
+    let res = resume(events, state, sidecar, flags).await;
+    settle(events, state, &id, &res);
+    res
+}
+
+async fn resume(
+    events: &Events,
+    state: &AppState,
+    mut sidecar: Sidecar,
+    flags: DownloadFlags,
+) -> Result<String, DownloadError> {
+    let client = sidecar.options.client()?;
+
+    validate_unchanged(&client, &sidecar).await?;
+    // A stopped download gave up its name (a paused one still holds its empty
+    // placeholder), so claim it again the way a new download would
+    conflict::release(&sidecar.dest);
+    let expected = sidecar
+        .expected_checksum
+        .as_deref()
+        .map(ExpectedChecksum::parse)
+        .transpose()?;
+    let claimed = conflict::resolve(
+        events,
+        state,
+        &sidecar.id,
+        sidecar.dest.clone(),
+        sidecar.total,
+        expected.as_ref(),
+    )
+    .await?;
+    match claimed {
+        Target::Write(dest) if dest != sidecar.dest => {
+            sidecar.dest = dest;
+            checkpoint(state, &sidecar)?;
+        }
+        Target::Write(_) => {}
+        Target::Existing(dest) => {
+            let _ = std::fs::remove_file(&sidecar.temp);
+            sidecar::remove(&state.dirs, &sidecar.id);
+            return conflict::keep_existing(events, state, &sidecar.id, &sidecar.url, dest, &flags);
+        }
+    }
+    let controls = register(state, &sidecar, flags)?;
+    let started = started_payload(&sidecar);
+    let resumed = ResumedPayload {
+        id: started.id,
+        url: started.url,
+        file_name: started.file_name,
+        dest_dir: started.dest_dir,
+        total: started.total,
+        received: sidecar.received(),
+    };
+
+    let resumable = sidecar.accept_ranges
+        && sidecar.total.is_some()
+        && !sidecar.segments.is_empty()
+        && sidecar.temp.exists();
+    if !resumable {
+        // Nothing we can safely keep: start over from the first byte
+        for seg in sidecar.segments.iter_mut() {
+            seg.done = 0;
+        }
+        events.resumed(&ResumedPayload {
+            received: 0,
+            ..resumed
+        });
+        return download_single(events, state, &client, sidecar, controls).await;
+    }
+
+    // A single-connection transfer only grew the file up to what it received
+    let total = sidecar.total.unwrap_or(0);
+    let file = OpenOptions::new()
+        .write(true)
+        .open(&sidecar.temp)
+        .map_err(|e| DownloadError::io("Open part file", e))?;
+    if file.metadata().map(|m| m.len()).unwrap_or(0) < total {
+        file.set_len(total)
+            .map_err(|e| DownloadError::io("Pre-allocate", e))?;
+    }
+    drop(file);
+    events.resumed(&resumed);
+    download_segments(events, state, &client, sidecar, controls).await
+}
+
+fn started_payload(sidecar: &Sidecar) -> StartedPayload {
+    let (file_name, dest_dir) = split_dest(&sidecar.dest);
+    StartedPayload {
+        id: sidecar.id.clone(),
+        url: sidecar.url.clone(),
+        file_name,
+        dest_dir,
+        total: sidecar.total,
+    }
+}
+
+/// What a running transfer consults on every chunk: whether it should stop,
+/// and the bandwidth limits it has to respect.
+#[derive(Clone)]
+struct Controls {
+    cancel: Arc<AtomicBool>,
+    pause: Arc<AtomicBool>,
+    /// Bytes on disk so far, read by the registry for `list_downloads`.
+    received: Arc<AtomicU64>,
+    throttle: Throttle,
+}
+
+impl Controls {
+    fn stop_requested(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed) || self.pause.load(Ordering::Relaxed)
+    }
+}
+
+fn register(state: &AppState, sidecar: &Sidecar, flags: DownloadFlags) -> Result<Controls, String> {
+    state
+        .downloads
+        .lock()
+        .map_err(|_| "State poisoned")?
+        .downloading(
+            &sidecar.id,
+            DownloadMeta {
+                url: sidecar.url.clone(),
+                dest: sidecar.dest.clone(),
+                temp: sidecar.temp.clone(),
+                total: sidecar.total,
+                accept_ranges: sidecar.accept_ranges,
+                etag: sidecar.etag.clone(),
+                last_modified: sidecar.last_modified.clone(),
+            },
+        )?;
+    Ok(Controls {
+        cancel: flags.cancel,
+        pause: flags.pause,
+        received: flags.received,
+        throttle: state.throttle_for(&sidecar.id),
+    })
+}
+
+/// Save progress to the sidecar unless the download was deleted meanwhile.
+/// Saving under the registry lock keeps `delete_download` from removing the
+/// sidecar between the check and the write.
+fn checkpoint(state: &AppState, sidecar: &Sidecar) -> Result<(), String> {
+    let downloads = state.downloads.lock().map_err(|_| "State poisoned")?;
+    if !downloads.contains(&sidecar.id) {
+        return Ok(());
+    }
+    sidecar::save(&state.dirs, sidecar)
+}
+
+/// Persist progress and report the pause; the registry keeps the paused
+/// download so it can still be deleted along with its files.
+fn finish_paused(
+    events: &Events,
+    state: &AppState,
+    sidecar: &Sidecar,
+) -> Result<String, DownloadError> {
+    let _ = checkpoint(state, sidecar);
+    let payload = PausedPayload {
+        id: sidecar.id.clone(),
+        received: sidecar.received(),
+        total: sidecar.total,
+    };
+    events.paused(&payload);
+    Err(DownloadError::paused())
+}
+
+/// Persist progress and report the cancel. A download canceled by
+/// `delete_download` is already gone from the registry: its `.part` file is
+/// removed here, once nothing holds it open any more.
+fn finish_canceled(
+    events: &Events,
+    state: &AppState,
+    sidecar: &Sidecar,
+) -> Result<String, DownloadError> {
+    if let Ok(downloads) = state.downloads.lock() {
+        if !downloads.contains(&sidecar.id) {
+            let _ = std::fs::remove_file(&sidecar.temp);
+            conflict::release(&sidecar.dest);
+            sidecar::remove(&state.dirs, &sidecar.id);
+        } else if !sidecar.segments.is_empty() {
+            let _ = sidecar::save(&state.dirs, sidecar);
+        }
+    }
+    events.canceled(&CanceledPayload {
+        id: sidecar.id.clone(),
+    });
+    Err(DownloadError::canceled())
+}
+
+/// Make sure the remote file is still the one the sidecar was recorded against,
+/// probing it the same way a new download does.
+async fn validate_unchanged(
+    client: &reqwest::Client,
+    sidecar: &Sidecar,
+) -> Result<(), DownloadError> {
+    let info = probe::probe(client, &sidecar.options, &sidecar.url).await?;
+    // A strong validator wins; only fall back to Last-Modified when ETags are unavailable
+    match (
+        &sidecar.etag,
+        &info.etag,
+        &sidecar.last_modified,
+        &info.last_modified,
+    ) {
+        (Some(old), Some(new), _, _) if old != new => {
+            return Err(DownloadError::server_changed(
+                "Server resource changed (ETag mismatch)",
+            ));
+        }
+        (Some(_), Some(_), _, _) => {}
+        (_, _, Some(old), Some(new)) if old != new => {
+            return Err(DownloadError::server_changed(
+                "Server resource changed (Last-Modified mismatch)",
+            ));
+        }
+        _ => {}
+    }
+    match (sidecar.total, info.total) {
+        (Some(old), Some(new)) if old != new => Err(DownloadError::server_changed(format!(
+            "Server resource changed (size {} != {})",
+            new, old
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Validator sent as `If-Range` so a changed resource comes back as a full 200
+/// instead of a range of the new version. Weak ETags are not allowed there.
+fn if_range_validator(sidecar: &Sidecar) -> Option<String> {
+    sidecar
+        .etag
+        .clone()
+        .filter(|e| !e.starts_with("W/"))
+        .or_else(|| sidecar.last_modified.clone())
+}
+
+fn snapshot(sidecar: &Sidecar, table: &Mutex<SegmentTable>) -> Sidecar {
+    let mut snap = sidecar.clone();
+    if let Ok(table) = table.lock() {
+        snap.segments = table.segments.clone();
+    }
+    snap
+}
+
+/// Move the finished `.part` into place and, when a checksum was requested,
+/// verify it. A mismatch fails the download but keeps the file for inspection.
+async fn finish_completed(
+    events: &Events,
+    state: &AppState,
+    sidecar: &Sidecar,
+) -> Result<String, DownloadError> {
+    let id = &sidecar.id;
+    let dest = &sidecar.dest;
+    // Replaces the name reserved when the download started or resumed
+    std::fs::rename(&sidecar.temp, dest).map_err(|e| DownloadError::io("Rename", e))?;
+    sidecar::remove(&state.dirs, id);
+    if let Some(expected) = sidecar.expected_checksum.as_deref() {
+        let expected = ExpectedChecksum::parse(expected)?;
+        state
+            .downloads
+            .lock()
+            .map_err(|_| "State poisoned")?
+            .verifying(id)?;
+        checksum::verify(events, id, dest, &expected).await?;
+    }
+    let complete = CompletedPayload {
+        id: id.clone(),
+        path: dest.to_string_lossy().to_string(),
+    };
+    events.completed(&complete);
+    Ok(dest.to_string_lossy().to_string())
+}
+
+/// Stream the whole body over one connection, restarting the `.part` file from scratch.
+async fn download_single(
+    events: &Events,
+    state: &AppState,
+    client: &reqwest::Client,
+    mut sidecar: Sidecar,
+    controls: Controls,
+) -> Result<String, DownloadError> {
+    let id = sidecar.id.clone();
+    let temp = sidecar.temp.clone();
+    let len_local = sidecar.total;
+    let resp = sidecar
+        .options
+        .get(client, &sidecar.url)
+        .send()
+        .await
+        .map_err(|e| DownloadError::request("GET", e))?;
+    if !resp.status().is_success() {
+        return Err(DownloadError::status(resp.status().as_u16()));
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(&temp)
+        .map_err(|e| DownloadError::io("Open file", e))?;
+    let mut stream = resp.bytes_stream();
+    let total = len_local.unwrap_or(0);
+    let mut received_all: u64 = 0;
+    let mut last_instant = Instant::now();
+    let mut last_saved = Instant::now();
+    let mut last_bytes = 0u64;
+    while let Some(chunk) = stream.next().await {
+        if controls.stop_requested() {
+            break;
+        }
+        let bytes = chunk.map_err(|e| DownloadError::request("Read stream", e))?;
+        controls.throttle.consume(bytes.len() as u64).await;
+        file.write_all(&bytes)
+            .map_err(|e| DownloadError::io("Write", e))?;
+        received_all += bytes.len() as u64;
+        controls.received.store(received_all, Ordering::Relaxed);
+        if let Some(seg) = sidecar.segments.first_mut() {
+            seg.done = received_all;
+        }
+        let now = Instant::now();
+        if now.duration_since(last_instant) >= Duration::from_millis(400) {
+            let delta = received_all.saturating_sub(last_bytes);
+            let elapsed = now.duration_since(last_instant).as_secs_f64().max(0.001);
+            let speed = (delta as f64 / elapsed) as u64;
+            let payload = ProgressPayload {
+                id: id.clone(),
+                received: received_all,
+                total,
+                speed,
+                retries: 0,
+            };
+            events.progress(&payload);
+            last_instant = now;
+            last_bytes = received_all;
+        }
+        if !sidecar.segments.is_empty() && now.duration_since(last_saved) >= SIDECAR_SAVE_INTERVAL {
+            let _ = checkpoint(state, &sidecar);
+            last_saved = now;
+        }
+    }
+    drop(file);
+    if controls.pause.load(Ordering::Relaxed) && !controls.cancel.load(Ordering::Relaxed) {
+        return finish_paused(events, state, &sidecar);
+    }
+    if controls.cancel.load(Ordering::Relaxed) {
+        return finish_canceled(events, state, &sidecar);
+    }
+    if received_all == 0 {
+        let _ = std::fs::remove_file(&temp);
+        sidecar::remove(&state.dirs, &id);
+        return Err("no data received".into());
+    }
+    finish_completed(events, state, &sidecar).await
+}
+
+/// Fetch every unfinished segment of a pre-allocated `.part` file in parallel,
+/// checkpointing per-segment progress to the sidecar as it goes.
+async fn download_segments(
+    events: &Events,
+    state: &AppState,
+    client: &reqwest::Client,
+    sidecar: Sidecar,
+    controls: Controls,
+) -> Result<String, DownloadError> {
+    let id = sidecar.id.clone();
+    let url = sidecar.url.clone();
+    let temp = sidecar.temp.clone();
+    let total = sidecar
+        .total
+        .ok_or_else(|| "Server didn't provide content length".to_string())?;
+
+    let table = Arc::new(Mutex::new(SegmentTable::new(sidecar.segments.clone())));
+    let downloaded = controls.received.clone();
+    downloaded.store(sidecar.received(), Ordering::Relaxed);
+    let retries = Arc::new(AtomicU32::new(0));
+    let finished = Arc::new(AtomicBool::new(false));
+    let policy = state
+        .settings
+        .lock()
+        .map_err(|_| "State poisoned")?
+        .retry_policy();
+    let dl_for_ticker = downloaded.clone();
+    let retries_for_ticker = retries.clone();
+    let finished_for_ticker = finished.clone();
+    let table_for_ticker = table.clone();
+    let sidecar_for_ticker = sidecar.clone();
+
+    let events_for_ticker = events.clone();
+    let id_for_ticker = id.clone();
+    let mut last_bytes = downloaded.load(Ordering::Relaxed);
+    let mut last_instant = Instant::now();
+    let mut last_saved = Instant::now();
+    let controls_for_ticker = controls.clone();
+    // Runs on this task rather than spawned, so its checkpoints can consult the registry
+    let ticker = async move {
+        let mut interval = time::interval(Duration::from_millis(500));
+        loop {
+            interval.tick().await;
+            let cur = dl_for_ticker.load(Ordering::Relaxed);
+            let now = Instant::now();
+            let delta = cur.saturating_sub(last_bytes);
+            let elapsed = now.duration_since(last_instant).as_secs_f64().max(0.001);
+            let speed = (delta as f64 / elapsed) as u64;
+            let payload = ProgressPayload {
+                id: id_for_ticker.clone(),
+                received: cur,
+                total,
+                speed,
+                retries: retries_for_ticker.load(Ordering::Relaxed),
+            };
+            events_for_ticker.progress(&payload);
+            last_bytes = cur;
+            last_instant = now;
+            if now.duration_since(last_saved) >= SIDECAR_SAVE_INTERVAL {
+                let _ = checkpoint(state, &snapshot(&sidecar_for_ticker, &table_for_ticker));
+                last_saved = now;
+            }
+            if cur >= total
+                || controls_for_ticker.stop_requested()
+                || finished_for_ticker.load(Ordering::Relaxed)
+            {
+                break;
+            }
+        }
+    };
+    let shared = SegmentShared {
+        client: client.clone(),
+        url: url.clone(),
+        options: sidecar.options.clone(),
+        temp: temp.clone(),
+        table: table.clone(),
+        downloaded: downloaded.clone(),
+        controls: controls.clone(),
+        retries: retries.clone(),
+        failed: Arc::new(AtomicBool::new(false)),
+        changed: Arc::new(AtomicBool::new(false)),
+        unranged: Arc::new(AtomicBool::new(false)),
+        total,
+        if_range: if_range_validator(&sidecar),
+        policy,
+    };
+    let workers = if sidecar.connections > 0 {
+        sidecar.connections
+    } else {
+        sidecar.segments.len()
+    };
+    let mut tasks = Vec::new();
+    for _ in 0..workers.max(1) {
+        tasks.push(tokio::spawn(run_worker(shared.clone())));
+    }
+
+    let workers = async {
+        let mut any_err: Option<DownloadError> = None;
+        for t in tasks {
+            match t.await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => any_err = Some(e),
+                Err(e) => any_err = Some(format!("Join error: {}", e).into()),
+            }
+        }
+        // Also on a join error, or the ticker would never stop
+        finished.store(true, Ordering::Relaxed);
+        any_err
+    };
+    let (any_err, ()) = tokio::join!(workers, ticker);
+
+    if controls.pause.load(Ordering::Relaxed) && !controls.cancel.load(Ordering::Relaxed) {
+        return finish_paused(events, state, &snapshot(&sidecar, &table));
+    }
+    if controls.cancel.load(Ordering::Relaxed) {
+        return finish_canceled(events, state, &snapshot(&sidecar, &table));
+    }
+
+    if any_err.is_some()
+        && shared.unranged.load(Ordering::Relaxed)
+        && !shared.changed.load(Ordering::Relaxed)
+    {
+        // Ranges were advertised but not honored: start over on one connection
+        let mut single = sidecar.clone();
+        single.accept_ranges = false;
+        single.connections = 1;
+        single.segments = vec![SegmentRecord {
+            start: 0,
+            end: total - 1,
+            done: 0,
+        }];
+        let _ = checkpoint(state, &single);
+        return download_single(events, state, client, single, controls).await;
+    }
+    if let Some(err) = any_err {
+        if shared.changed.load(Ordering::Relaxed) {
+            // The bytes on disk mix two versions of the file; nothing is worth resuming
+            let _ = std::fs::remove_file(&temp);
+            sidecar::remove(&state.dirs, &id);
+        } else {
+            // Keep what we have so the download can be resumed later
+            let _ = checkpoint(state, &snapshot(&sidecar, &table));
+        }
+        return Err(err);
+    }
+    // Validate completeness
+    let got = downloaded.load(Ordering::Relaxed);
+    if got < total {
+        let _ = checkpoint(state, &snapshot(&sidecar, &table));
+        return Err(DownloadError::new(
+            ErrorKind::Network,
+            format!("incomplete: {} < {}", got, total),
+        ));
+    }
+    finish_completed(events, state, &sidecar).await
+}
+
+/// Below this many remaining bytes a segment is not worth splitting for another connection.
+const MIN_SPLIT_SIZE: u64 = 512 * 1024;
+
+/// Live segment layout of a download. Bytes are reserved under the lock before
+/// they are written, so a split can never hand out a range someone is writing.
+struct SegmentTable {
+    segments: Vec<SegmentRecord>,
+    busy: Vec<bool>,
+}
+
+impl SegmentTable {
+    fn new(segments: Vec<SegmentRecord>) -> Self {
+        let busy = vec![false; segments.len()];
+        Self { segments, busy }
+    }
+
+    /// Pick work for an idle connection: an unfinished segment nobody owns, or
+    /// else the back half of the largest range still being downloaded.
+    fn claim(&mut self) -> Option<usize> {
+        if let Some(idx) =
+            (0..self.segments.len()).find(|&i| !self.busy[i] && !self.segments[i].is_complete())
+        {
+            self.busy[idx] = true;
+            return Some(idx);
+        }
+        let (victim, remaining) = self
+            .segments
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| self.busy[*i])
+            .map(|(i, s)| (i, (s.end + 1).saturating_sub(s.start + s.done)))
+            .max_by_key(|(_, remaining)| *remaining)?;
+        if remaining < 2 * MIN_SPLIT_SIZE {
+            return None;
+        }
+        let seg = &mut self.segments[victim];
+        let mid = seg.start + seg.done + remaining / 2;
+        let end = seg.end;
+        seg.end = mid - 1;
+        self.segments.push(SegmentRecord {
+            start: mid,
+            end,
+            done: 0,
+        });
+        self.busy.push(true);
+        Some(self.segments.len() - 1)
+    }
+
+    /// Reserve up to `len` bytes at the segment's current position; returns the
+    /// file offset and how many of the bytes still belong to the segment.
+    fn reserve(&mut self, idx: usize, len: u64) -> (u64, u64) {
+        let seg = &mut self.segments[idx];
+        let pos = seg.start + seg.done;
+        let allowed = len.min((seg.end + 1).saturating_sub(pos));
+        seg.done += allowed;
+        (pos, allowed)
+    }
+}
+
+/// Handles shared by every connection of one download.
+#[derive(Clone)]
+struct SegmentShared {
+    client: reqwest::Client,
+    url: String,
+    options: RequestOptions,
+    temp: PathBuf,
+    table: Arc<Mutex<SegmentTable>>,
+    downloaded: Arc<AtomicU64>,
+    controls: Controls,
+    retries: Arc<AtomicU32>,
+    failed: Arc<AtomicBool>,
+    /// Set when a range request revealed that the resource changed on the server.
+    changed: Arc<AtomicBool>,
+    /// Set when the server answered a range request with the full body.
+    unranged: Arc<AtomicBool>,
+    total: u64,
+    if_range: Option<String>,
+    policy: RetryPolicy,
+}
+
+impl SegmentShared {
+    fn claim(&self) -> Option<usize> {
+        if self.failed.load(Ordering::Relaxed) || self.controls.stop_requested() {
+            return None;
+        }
+        self.table.lock().ok()?.claim()
+    }
+
+    fn release(&self, idx: usize) {
+        if let Ok(mut table) = self.table.lock() {
+            table.busy[idx] = false;
+        }
+    }
+
+    /// Current `(position, end)` of a segment.
+    fn bounds(&self, idx: usize) -> Option<(u64, u64)> {
+        let table = self.table.lock().ok()?;
+        let seg = &table.segments[idx];
+        Some((seg.start + seg.done, seg.end))
+    }
+}
+
+/// One connection: keep claiming segments (stealing from the slowest once the
+/// initial ones run out) until nothing worth downloading is left.
+async fn run_worker(shared: SegmentShared) -> Result<(), DownloadError> {
+    while let Some(idx) = shared.claim() {
+        let res = run_segment(&shared, idx).await;
+        shared.release(idx);
+        if res.is_err() {
+            shared.failed.store(true, Ordering::Relaxed);
+            return res;
+        }
+    }
+    Ok(())
+}
+
+/// Download segment `idx`, retrying transient failures from the last written
+/// offset with exponential backoff.
+async fn run_segment(shared: &SegmentShared, idx: usize) -> Result<(), DownloadError> {
+    let mut attempt = 0u32;
+    loop {
+        let (offset, end) = shared.bounds(idx).ok_or("State poisoned")?;
+        if offset > end || shared.controls.stop_requested() {
+            return Ok(());
+        }
+        let failure = match fetch_range(shared, idx, offset, end).await {
+            Ok(()) => return Ok(()),
+            Err(f) => f,
+        };
+        if shared.controls.stop_requested() {
+            return Ok(());
+        }
+        // Only count consecutive attempts that made no progress at all
+        if shared.bounds(idx).is_some_and(|(pos, _)| pos > offset) {
+            attempt = 0;
+        }
+        attempt += 1;
+        if !failure.retryable || attempt > shared.policy.max_retries {
+            return Err(failure.error);
+        }
+        shared.retries.fetch_add(1, Ordering::Relaxed);
+        let delay = shared.policy.delay(attempt, failure.retry_after);
+        if !sleep_unless_stopped(delay, &shared.controls).await {
+            return Ok(());
+        }
+    }
+}
+
+/// One request for `[start, end]`. The segment's end may shrink while the
+/// response streams in (another connection took over its tail), in which case
+/// the connection is dropped as soon as the shortened range is filled.
+async fn fetch_range(
+    shared: &SegmentShared,
+    idx: usize,
+    start: u64,
+    end: u64,
+) -> Result<(), SegmentFailure> {
+    let range_header = format!("bytes={}-{}", start, end);
+    let mut req = shared
+        .options
+        .get(&shared.client, &shared.url)
+        .header(RANGE, range_header);
+    if let Some(validator) = &shared.if_range {
+        req = req.header(IF_RANGE, validator);
+    }
+    let resp = req
+        .send()
+        .await
+        .map_err(|e| DownloadError::request("Range GET", e))?;
+    let status = resp.status();
+    if status == StatusCode::OK {
+        if let Some(validator) = &shared.if_range
+            && !has_validator(resp.headers(), validator)
+        {
+            // If-Range didn't match: the server sent the whole new version instead
+            shared.changed.store(true, Ordering::Relaxed);
+            return Err(SegmentFailure::fatal(DownloadError::server_changed(
+                "Server resource changed during download; restart it",
+            )));
+        }
+        // Same file, but the server doesn't honor Range after all
+        shared.unranged.store(true, Ordering::Relaxed);
+        return Err(SegmentFailure::fatal(
+            "Server ignored the Range request".into(),
+        ));
+    }
+    if !status.is_success() {
+        return Err(SegmentFailure::from_status(status, resp.headers()));
+    }
+    if status != StatusCode::PARTIAL_CONTENT {
+        return Err(SegmentFailure::fatal(
+            format!("Expected 206 Partial Content, got {}", status).into(),
+        ));
+    }
+    let content_range = header_string(resp.headers(), CONTENT_RANGE).unwrap_or_default();
+    match parse_content_range(&content_range) {
+        Some((first, last, complete))
+            if first == start && last == end && complete.is_none_or(|t| t == shared.total) => {}
+        _ => {
+            return Err(SegmentFailure::fatal(
+                format!(
+                    "Content-Range '{}' doesn't match requested bytes={}-{}",
+                    content_range, start, end
+                )
+                .into(),
+            ));
+        }
+    }
+
+    let mut f = OpenOptions::new()
+        .write(true)
+        .open(&shared.temp)
+        .map_err(|e| DownloadError::io("Open part file", e))?;
+    f.seek(SeekFrom::Start(start))
+        .map_err(|e| DownloadError::io("Seek", e))?;
+
+    let mut stream = resp.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        if shared.controls.stop_requested() {
+            return Ok(());
+        }
+        let bytes = chunk.map_err(|e| DownloadError::request("Read stream", e))?;
+        shared.controls.throttle.consume(bytes.len() as u64).await;
+        let (pos, allowed) = shared
+            .table
+            .lock()
+            .map_err(|_| SegmentFailure::fatal("State poisoned".into()))?
+            .reserve(idx, bytes.len() as u64);
+        if let Err(e) = f.write_all(&bytes[..allowed as usize]) {
+            // Give the reservation back so a resume re-fetches these bytes
+            if let Ok(mut table) = shared.table.lock() {
+                table.segments[idx].done -= allowed;
+            }
+            return Err(DownloadError::io("Write", e).into());
+        }
+        shared.downloaded.fetch_add(allowed, Ordering::Relaxed);
+        if pos + allowed > end || allowed < bytes.len() as u64 {
+            return Ok(());
+        }
+    }
+    match shared.bounds(idx) {
+        Some((pos, end)) if pos <= end => Err(DownloadError::new(
+            ErrorKind::Network,
+            format!(
+                "connection closed at byte {} of segment ending at {}",
+                pos, end
+            ),
+        )
+        .into()),
+        _ => Ok(()),
+    }
+}
+
+/// Parse `bytes first-last/complete`; the complete length may be `*`.
+fn parse_content_range(value: &str) -> Option<(u64, u64, Option<u64>)> {
+    let (range, complete) = value.trim().strip_prefix("bytes ")?.split_once('/')?;
+    let (first, last) = range.split_once('-')?;
+    let first = first.trim().parse::<u64>().ok()?;
+    let last = last.trim().parse::<u64>().ok()?;
+    let complete = match complete.trim() {
+        "*" => None,
+        n => Some(n.parse::<u64>().ok()?),
+    };
+    (first <= last).then_some((first, last, complete))
+}
+
+/// Whether a response still carries the ETag or Last-Modified we sent in If-Range.
+fn has_validator(headers: &HeaderMap, validator: &str) -> bool {
+    header_string(headers, ETAG).as_deref() == Some(validator)
+        || header_string(headers, LAST_MODIFIED).as_deref() == Some(validator)
+}
+
+/// Sleep for `delay` in short steps; returns false if the download was stopped meanwhile.
+async fn sleep_unless_stopped(delay: Duration, controls: &Controls) -> bool {
+    let deadline = Instant::now() + delay;
+    while !controls.stop_requested() {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return true;
+        }
+        time::sleep(left.min(Duration::from_millis(200))).await;
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::testing::{self, Behavior};
+
+    fn request(url: String, dir: &std::path::Path) -> DownloadRequest {
+        DownloadRequest {
+            url,
+            threads: 4,
+            dest_dir: Some(dir.to_string_lossy().to_string()),
+            file_name: Some("file.bin".into()),
+            expected_checksum: None,
+            options: Default::default(),
+            resource: None,
+        }
+    }
+
+    async fn download_from(name: &str, behavior: Behavior) {
+        let body = testing::body(300_000);
+        let url = testing::serve(body.clone(), behavior);
+        let dir = testing::temp_dir(name);
+        let state = testing::state();
+        let path = run_download(&testing::events(), &state, None, request(url, &dir))
+            .await
+            .expect("download succeeds");
+        assert_eq!(std::fs::read(&path).expect("read result"), body);
+        // Only the finished file is left behind
+        let names: Vec<_> = std::fs::read_dir(&dir)
+            .expect("list dir")
+            .flatten()
+            .map(|e| e.file_name())
+            .collect();
+        assert_eq!(names, ["file.bin"]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn downloads_in_segments_from_a_range_server() {
+        download_from(
+            "ranges",
+            Behavior {
+                ranges: true,
+                ..Default::default()
+            },
+        )
+        .await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn downloads_whole_body_when_range_is_ignored() {
+        download_from("no-ranges", Behavior::default()).await;
+    }
+
+    fn sidecar_for(url: String, total: u64) -> Sidecar {
+        Sidecar {
+            id: "dl-test".into(),
+            url,
+            dest: PathBuf::from("file.bin"),
+            temp: PathBuf::from("file.bin.part"),
+            total: Some(total),
+            accept_ranges: true,
+            etag: None,
+            last_modified: None,
+            connections: 4,
+            expected_checksum: None,
+            options: Default::default(),
+            segments: Vec::new(),
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn resume_validation_survives_servers_that_refuse_head() {
+        let behavior = Behavior {
+            ranges: true,
+            refuse_head: true,
+        };
+        let url = testing::serve(testing::body(1000), behavior);
+        let client = reqwest::Client::new();
+        validate_unchanged(&client, &sidecar_for(url.clone(), 1000))
+            .await
+            .expect("same file");
+        let changed = validate_unchanged(&client, &sidecar_for(url, 999))
+            .await
+            .expect_err("size changed");
+        assert_eq!(changed.kind, ErrorKind::ServerChanged);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn resume_does_not_replace_a_file_that_took_the_name() {
+        let state = testing::state();
+        let body = testing::body(50_000);
+        let url = testing::serve(
+            body.clone(),
+            Behavior {
+                ranges: true,
+                ..Default::default()
+            },
+        );
+        let dir = testing::temp_dir("resume-conflict");
+        let mut sidecar = sidecar_for(url, body.len() as u64);
+        sidecar.id = "dl-resume-conflict".into();
+        sidecar.dest = dir.join("file.bin");
+        sidecar.temp = conflict::temp_path(&sidecar.dest, &sidecar.id);
+        sidecar.segments = vec![SegmentRecord {
+            start: 0,
+            end: body.len() as u64 - 1,
+            done: 0,
+        }];
+        std::fs::write(&sidecar.temp, b"").expect("write part file");
+        sidecar::save(&state.dirs, &sidecar).expect("save sidecar");
+        // Something else saved a file under the name while the download was stopped
+        std::fs::write(&sidecar.dest, b"not ours").expect("write other file");
+
+        let path = run_resume(&testing::events(), &state, sidecar.id.clone())
+            .await
+            .expect("resume succeeds");
+        assert_eq!(PathBuf::from(&path), dir.join("file (1).bin"));
+        assert_eq!(std::fs::read(&path).expect("read result"), body);
+        assert_eq!(
+            std::fs::read(&sidecar.dest).expect("read other file"),
+            b"not ours"
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn deleting_a_running_download_leaves_nothing_behind() {
+        let state = Arc::new(testing::state());
+        let id = "dl-deleted".to_string();
+        // Slow enough to still be running after a sidecar checkpoint
+        state.limiter_for(&id).set_rate(40_000);
+        let url = testing::serve(
+            testing::body(400_000),
+            Behavior {
+                ranges: true,
+                ..Default::default()
+            },
+        );
+        let dir = testing::temp_dir("deleted");
+        let task = {
+            let (state, id, request) = (state.clone(), id.clone(), request(url, &dir));
+            tokio::spawn(async move {
+                run_download(&testing::events(), &state, Some(id), request).await
+            })
+        };
+        time::sleep(SIDECAR_SAVE_INTERVAL + Duration::from_millis(700)).await;
+        assert!(
+            sidecar::load(&state.dirs, &id).is_ok(),
+            "checkpointed while running"
+        );
+
+        // What `delete_download` does, while the transfer still has the files open
+        let meta = state
+            .downloads
+            .lock()
+            .unwrap()
+            .remove(&id)
+            .expect("still running");
+        let _ = std::fs::remove_file(&meta.temp);
+        conflict::release(&meta.dest);
+        sidecar::remove(&state.dirs, &id);
+
+        let res = task.await.expect("join");
+        assert_eq!(res.expect_err("canceled").kind, ErrorKind::Canceled);
+        assert!(
+            sidecar::load(&state.dirs, &id).is_err(),
+            "no sidecar comes back"
+        );
+        assert_eq!(std::fs::read_dir(&dir).expect("list dir").count(), 0);
+    }
+}
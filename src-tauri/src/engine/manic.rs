@@ -0,0 +1,303 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::{
+    fs::OpenOptions,
+    io::{Seek, SeekFrom, Write},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use futures_util::StreamExt;
+use manic::Downloader as ManicDownloader;
+use reqwest::header::RANGE;
+use tokio::time::{self, Duration, Instant};
+
+use crate::checksum::{self, ExpectedChecksum};
+use crate::error::{DownloadError, ErrorKind};
+use crate::filename;
+use crate::limiter::Throttle;
+use crate::payloads::{
+    CanceledPayload, CompletedPayload, DownloadRequest, ProgressPayload, StartedPayload,
+};
+use crate::registry::DownloadFlags;
+use crate::request::RequestOptions;
+use crate::state::{AppState, DownloadMeta};
+use crate::util::{new_download_id, split_dest};
+
+use super::conflict::{self, Target};
+use super::events::Events;
+use super::settle;
+
+/// Download `url` with the manic engine; `id` is pre-assigned when the download comes from the queue.
+pub async fn run_download_manic(
+    events: &Events,
+    state: &AppState,
+    id: Option<String>,
+    request: DownloadRequest,
+) -> Result<String, DownloadError> {
+    let id = id.unwrap_or_else(new_download_id);
+    let flags = state
+        .downloads
+        .lock()
+        .map_err(|_| "State poisoned")?
+        .begin(&id, &request.url, false)?;
+    let res = download(events, state, id.clone(), flags, request).await;
+    settle(events, state, &id, &res);
+    res
+}
+
+async fn download(
+    events: &Events,
+    state: &AppState,
+    id: String,
+    flags: DownloadFlags,
+    request: DownloadRequest,
+) -> Result<String, DownloadError> {
+    let DownloadRequest {
+        url,
+        threads,
+        dest_dir,
+        file_name,
+        expected_checksum,
+        options,
+        ..
+    } = request;
+    let expected = expected_checksum
+        .as_deref()
+        .map(ExpectedChecksum::parse)
+        .transpose()?;
+    let workers = threads.clamp(1, 32);
+
+    // Initialize manic downloader
+    let dl = ManicDownloader::new(&url, workers)
+        .await
+        .map_err(|e| DownloadError::new(ErrorKind::Network, format!("manic init error: {}", e)))?;
+    let total = dl.get_len();
+
+    // Filename
+    let decided_name = filename::sanitize(file_name.as_deref().unwrap_or(dl.filename()));
+
+    // Destination directory
+    let mut base_dir: PathBuf = if let Some(custom) = dest_dir {
+        PathBuf::from(custom)
+    } else {
+        dirs::download_dir()
+            .or_else(dirs::home_dir)
+            .or_else(|| std::env::current_dir().ok())
+            .ok_or_else(|| "Cannot resolve a writable directory".to_string())?
+    };
+    std::fs::create_dir_all(&base_dir).map_err(|e| DownloadError::io("Create dir", e))?;
+    base_dir.push(decided_name);
+    let dest = match conflict::resolve(events, state, &id, base_dir, Some(total), expected.as_ref())
+        .await?
+    {
+        Target::Write(dest) => dest,
+        Target::Existing(dest) => {
+            return conflict::keep_existing(events, state, &id, &url, dest, &flags);
+        }
+    };
+    let temp = conflict::temp_path(&dest, &id);
+
+    let (file_name, dest_dir) = split_dest(&dest);
+    let started = StartedPayload {
+        id: id.clone(),
+        url: url.clone(),
+        file_name,
+        dest_dir,
+        total: Some(total),
+    };
+    events.started(&started);
+
+    state
+        .downloads
+        .lock()
+        .map_err(|_| "State poisoned")?
+        .downloading(
+            &id,
+            DownloadMeta {
+                url: url.clone(),
+                dest: dest.clone(),
+                temp: temp.clone(),
+                total: Some(total),
+                accept_ranges: true,
+                etag: None,
+                last_modified: None,
+            },
+        )?;
+    let cancel_flag = flags.cancel;
+
+    // Manic downloads can't be resumed, so a transfer that doesn't finish
+    // leaves nothing worth keeping behind
+    let transferred: Result<(), DownloadError> = async {
+        // Pre-allocate so every chunk can be written straight to its own offset
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&temp)
+            .map_err(|e| DownloadError::io("Open file", e))?;
+        file.set_len(total)
+            .map_err(|e| DownloadError::io("Pre-allocate", e))?;
+        drop(file);
+
+        let downloaded = flags.received;
+        let done_flag = Arc::new(AtomicBool::new(false));
+        let done_for_ticker = done_flag.clone();
+        let dl_for_ticker = downloaded.clone();
+        let events_for_ticker = events.clone();
+        let id_for_ticker = id.clone();
+        let mut last_bytes: u64 = 0;
+        let mut last_instant = Instant::now();
+        let cancel_for_ticker = cancel_flag.clone();
+        let progress_task = tokio::spawn(async move {
+            let mut interval = time::interval(Duration::from_millis(500));
+            loop {
+                interval.tick().await;
+                let cur = dl_for_ticker.load(Ordering::Relaxed);
+                let now = Instant::now();
+                let delta = cur.saturating_sub(last_bytes);
+                let elapsed = now.duration_since(last_instant).as_secs_f64().max(0.001);
+                let speed = (delta as f64 / elapsed) as u64;
+                let payload = ProgressPayload {
+                    id: id_for_ticker.clone(),
+                    received: cur,
+                    total,
+                    speed,
+                    retries: 0,
+                };
+                events_for_ticker.progress(&payload);
+                last_bytes = cur;
+                last_instant = now;
+                if done_for_ticker.load(Ordering::Relaxed)
+                    || cancel_for_ticker.load(Ordering::Relaxed)
+                    || cur >= total
+                {
+                    break;
+                }
+            }
+        });
+
+        // Same chunking as manic, but each chunk is streamed to disk as it arrives
+        // instead of being buffered in memory. manic's own length probe can't carry
+        // the browser context, but every chunk request does.
+        let client = options.client()?;
+        let chunk_size = (total / workers as u64).max(1);
+        let mut tasks = Vec::new();
+        for low in (0..total).step_by(chunk_size as usize) {
+            let hi = (low + chunk_size - 1).min(total - 1);
+            tasks.push(tokio::spawn(stream_chunk(
+                client.clone(),
+                url.clone(),
+                options.clone(),
+                temp.clone(),
+                low,
+                hi,
+                total,
+                downloaded.clone(),
+                cancel_flag.clone(),
+                state.throttle_for(&id),
+            )));
+        }
+        let mut any_err: Option<DownloadError> = None;
+        for t in tasks {
+            if let Err(e) = t.await.map_err(|e| format!("Join error: {}", e))? {
+                any_err = Some(e);
+            }
+        }
+        done_flag.store(true, Ordering::Relaxed);
+        let _ = progress_task.await;
+
+        if cancel_flag.load(Ordering::Relaxed) {
+            events.canceled(&CanceledPayload { id: id.clone() });
+            return Err(DownloadError::canceled());
+        }
+        if let Some(err) = any_err {
+            return Err(err);
+        }
+        let got = downloaded.load(Ordering::Relaxed);
+        if got < total {
+            return Err(DownloadError::new(
+                ErrorKind::Network,
+                format!("incomplete: {} < {}", got, total),
+            ));
+        }
+        Ok(())
+    }
+    .await;
+    if let Err(error) = transferred {
+        let _ = std::fs::remove_file(&temp);
+        return Err(error);
+    }
+    // Replaces the name reserved when the download started
+    if let Err(e) = std::fs::rename(&temp, &dest) {
+        let _ = std::fs::remove_file(&temp);
+        return Err(DownloadError::io("Rename", e));
+    }
+    // On checksum mismatch the file stays in place for inspection
+    if let Some(expected) = &expected {
+        state
+            .downloads
+            .lock()
+            .map_err(|_| "State poisoned")?
+            .verifying(&id)?;
+        checksum::verify(events, &id, &dest, expected).await?;
+    }
+    let complete = CompletedPayload {
+        id: id.clone(),
+        path: dest.to_string_lossy().to_string(),
+    };
+    events.completed(&complete);
+    Ok(dest.to_string_lossy().to_string())
+}
+
+/// Stream bytes `[low, hi]` into `temp` at their own offset, stopping early on cancel.
+#[allow(clippy::too_many_arguments)]
+async fn stream_chunk(
+    client: reqwest::Client,
+    url: String,
+    options: RequestOptions,
+    temp: PathBuf,
+    low: u64,
+    hi: u64,
+    total: u64,
+    downloaded: Arc<AtomicU64>,
+    cancel_flag: Arc<AtomicBool>,
+    throttle: Throttle,
+) -> Result<(), DownloadError> {
+    let resp = options
+        .get(&client, &url)
+        .header(RANGE, format!("bytes={}-{}", low, hi))
+        .send()
+        .await
+        .map_err(|e| DownloadError::request("Range GET", e))?;
+    // A plain 200 is only acceptable when the chunk covers the whole file
+    let whole_file = low == 0 && hi + 1 == total;
+    if resp.status() != reqwest::StatusCode::PARTIAL_CONTENT
+        && !(whole_file && resp.status().is_success())
+    {
+        return Err(DownloadError::status(resp.status().as_u16()));
+    }
+    let mut f = OpenOptions::new()
+        .write(true)
+        .open(&temp)
+        .map_err(|e| DownloadError::io("Open part file", e))?;
+    f.seek(SeekFrom::Start(low))
+        .map_err(|e| DownloadError::io("Seek", e))?;
+    let mut pos = low;
+    let mut stream = resp.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        if cancel_flag.load(Ordering::Relaxed) {
+            break;
+        }
+        let bytes = chunk.map_err(|e| DownloadError::request("Read stream", e))?;
+        let take = (bytes.len() as u64).min(hi + 1 - pos) as usize;
+        throttle.consume(take as u64).await;
+        f.write_all(&bytes[..take])
+            .map_err(|e| DownloadError::io("Write", e))?;
+        pos += take as u64;
+        downloaded.fetch_add(take as u64, Ordering::Relaxed);
+        if pos > hi {
+            break;
+        }
+    }
+    Ok(())
+}
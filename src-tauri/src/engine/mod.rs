@@ -0,0 +1,46 @@
+//! The download engine, independent of Tauri: it reports through
+//! [`events::DownloadEvents`], so the app, the `adm` CLI and tests can each
+//! drive it with their own event receiver.
+
+pub mod conflict;
+pub mod disposition;
+pub mod events;
+pub mod http;
+pub mod manic;
+pub mod probe;
+#[cfg(test)]
+mod testing;
+
+use crate::error::{DownloadError, ErrorKind};
+use crate::payloads::FailedPayload;
+use crate::state::AppState;
+use events::Events;
+
+/// Report how a transfer task ended: failures go out as `download_failed`
+/// and every outcome is recorded in the registry. A finished download no
+/// longer needs its own speed limiter, and one that won't resume soon no
+/// longer holds its file name.
+fn settle(events: &Events, state: &AppState, id: &str, res: &Result<String, DownloadError>) {
+    if let Err(error) = res
+        && error.is_failure()
+    {
+        events.failed(&FailedPayload {
+            id: id.to_string(),
+            error: error.clone(),
+        });
+    }
+    if let Ok(mut downloads) = state.downloads.lock() {
+        if let Err(error) = res
+            && error.kind != ErrorKind::Paused
+            && let Some(dest) = downloads.dest(id)
+        {
+            conflict::release(&dest);
+        }
+        downloads.finish(id, res);
+    }
+    if res.is_ok()
+        && let Ok(mut limiters) = state.limiters.lock()
+    {
+        limiters.remove(id);
+    }
+}
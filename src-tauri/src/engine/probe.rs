@@ -0,0 +1,185 @@
+use reqwest::StatusCode;
+use reqwest::header::{
+    ACCEPT_RANGES, CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG,
+    HeaderMap, HeaderName, LAST_MODIFIED, RANGE, SERVER,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::error::DownloadError;
+use crate::filename;
+use crate::request::RequestOptions;
+use crate::util::now_ms;
+
+use super::disposition::{self, percent_decode_bytes};
+
+/// How long a probe handed back by `probe_url` is trusted when the download
+/// starts; older ones are redone.
+const MAX_AGE_MS: u64 = 5 * 60 * 1000;
+
+/// What the server says about a URL before any of its body is downloaded.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ResourceInfo {
+    /// The URL as requested.
+    pub url: String,
+    /// Where redirects ended up.
+    pub final_url: String,
+    pub total: Option<u64>,
+    pub accept_ranges: bool,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// `Content-Type` without parameters.
+    pub mime_type: Option<String>,
+    /// From `Content-Disposition`, else the last path segment of `final_url`;
+    /// always sanitized.
+    pub file_name: String,
+    pub server: Option<String>,
+    /// Unix time in milliseconds.
+    pub probed_at: u64,
+}
+
+impl ResourceInfo {
+    /// Whether this probe of `url` is recent enough to start a download from.
+    pub fn is_fresh_for(&self, url: &str) -> bool {
+        self.url == url && now_ms().saturating_sub(self.probed_at) < MAX_AGE_MS
+    }
+}
+
+/// HEAD `url`, then, when that leaves the size, range support or a real file
+/// name open, a one-byte ranged GET that also follows redirects the HEAD
+/// didn't get.
+pub async fn probe(
+    client: &reqwest::Client,
+    options: &RequestOptions,
+    url: &str,
+) -> Result<ResourceInfo, DownloadError> {
+    let head = options
+        .head(client, url)
+        .send()
+        .await
+        .map_err(|e| DownloadError::request("HEAD", e))?;
+    let head_status = head.status();
+    // Servers that refuse HEAD describe their error page, not the file
+    let empty = HeaderMap::new();
+    let headers = if head_status.is_success() {
+        head.headers()
+    } else {
+        &empty
+    };
+    let mut info = ResourceInfo {
+        url: url.to_string(),
+        final_url: head.url().to_string(),
+        total: headers
+            .get(CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|s| s.parse::<u64>().ok()),
+        accept_ranges: header_string(headers, ACCEPT_RANGES)
+            .is_some_and(|v| v.to_ascii_lowercase().contains("bytes")),
+        etag: header_string(headers, ETAG),
+        last_modified: header_string(headers, LAST_MODIFIED),
+        mime_type: mime_type(headers),
+        file_name: String::new(),
+        server: header_string(headers, SERVER),
+        probed_at: now_ms(),
+    };
+    let mut cd_name = disposition_name(headers);
+
+    let url_name = name_from_url(&info.final_url);
+    let needs_name = cd_name.is_none() && (!url_name.contains('.') || url_name == "download.bin");
+    if needs_name || info.total.is_none() || !info.accept_ranges {
+        match options
+            .get(client, url)
+            .header(RANGE, "bytes=0-0")
+            .send()
+            .await
+        {
+            Ok(resp) if resp.status().is_success() => {
+                let headers = resp.headers();
+                info.final_url = resp.url().to_string();
+                if let Some(name) = disposition_name(headers) {
+                    cd_name = Some(name);
+                }
+                // Content-Range: bytes 0-0/12345, or the whole body when the range was ignored
+                if info.total.is_none() {
+                    info.total = match header_string(headers, CONTENT_RANGE) {
+                        Some(v) => v
+                            .split('/')
+                            .nth(1)
+                            .and_then(|n| n.trim().parse::<u64>().ok()),
+                        None if resp.status() == StatusCode::OK => headers
+                            .get(CONTENT_LENGTH)
+                            .and_then(|v| v.to_str().ok())
+                            .and_then(|s| s.parse::<u64>().ok()),
+                        None => None,
+                    };
+                }
+                if resp.status() == StatusCode::PARTIAL_CONTENT
+                    || headers.contains_key(CONTENT_RANGE)
+                {
+                    info.accept_ranges = true;
+                }
+                info.etag = info.etag.or_else(|| header_string(headers, ETAG));
+                info.last_modified = info
+                    .last_modified
+                    .or_else(|| header_string(headers, LAST_MODIFIED));
+                info.mime_type = info.mime_type.or_else(|| mime_type(headers));
+                info.server = info.server.or_else(|| header_string(headers, SERVER));
+            }
+            Ok(resp) if !head_status.is_success() => {
+                return Err(DownloadError::status(resp.status().as_u16()));
+            }
+            Err(e) if !head_status.is_success() => {
+                return Err(DownloadError::request("GET", e));
+            }
+            _ => {}
+        }
+    } else if !head_status.is_success() {
+        return Err(DownloadError::status(head_status.as_u16()));
+    }
+    info.file_name = filename::sanitize(&cd_name.unwrap_or_else(|| name_from_url(&info.final_url)));
+    Ok(info)
+}
+
+pub fn header_string(headers: &HeaderMap, name: HeaderName) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.to_string())
+}
+
+fn mime_type(headers: &HeaderMap) -> Option<String> {
+    let value = header_string(headers, CONTENT_TYPE)?;
+    let essence = value.split(';').next().unwrap_or_default().trim();
+    (!essence.is_empty()).then(|| essence.to_ascii_lowercase())
+}
+
+/// Last path segment of `url`, percent-decoded; `download.bin` when there is none.
+pub fn name_from_url(url: &str) -> String {
+    let name = url::Url::parse(url)
+        .ok()
+        .and_then(|u| u.path_segments()?.next_back().map(percent_decode))
+        .unwrap_or_default();
+    if name.is_empty() {
+        "download.bin".into()
+    } else {
+        name
+    }
+}
+
+fn percent_decode(s: &str) -> String {
+    String::from_utf8(percent_decode_bytes(s.as_bytes())).unwrap_or_else(|_| s.to_string())
+}
+
+/// The name `Content-Disposition` asks for, reading raw bytes in the charset
+/// the `Content-Type` declares, if any.
+fn disposition_name(headers: &HeaderMap) -> Option<String> {
+    let charset = header_string(headers, CONTENT_TYPE).and_then(|value| {
+        value.split(';').skip(1).find_map(|param| {
+            let (name, value) = param.split_once('=')?;
+            name.trim()
+                .eq_ignore_ascii_case("charset")
+                .then(|| value.trim().trim_matches('"').to_string())
+        })
+    });
+    let value = headers.get(CONTENT_DISPOSITION)?;
+    disposition::filename(value.as_bytes(), charset.as_deref())
+}
@@ -0,0 +1,120 @@
+//! Local HTTP stubs and a sandboxed [`AppState`] for the engine's tests.
+
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock};
+
+use tiny_http::{Header, Method, Response, Server};
+
+use crate::state::AppState;
+use crate::util::AppDirs;
+
+use super::events::{DownloadEvents, Events};
+
+/// How the stub server answers.
+#[derive(Clone, Copy, Default)]
+pub struct Behavior {
+    /// Answer `Range` requests with 206; otherwise always send the whole body.
+    pub ranges: bool,
+    /// Answer HEAD with 403, like presigned URLs that only allow GET.
+    pub refuse_head: bool,
+}
+
+/// Serve `body` on a free localhost port until the test process exits and
+/// return its URL.
+pub fn serve(body: Vec<u8>, behavior: Behavior) -> String {
+    let server = Server::http("127.0.0.1:0").expect("bind stub server");
+    let url = format!("http://{}/file.bin", server.server_addr());
+    std::thread::spawn(move || {
+        for request in server.incoming_requests() {
+            let response = answer(&request, &body, behavior);
+            let _ = request.respond(response);
+        }
+    });
+    url
+}
+
+fn answer(
+    request: &tiny_http::Request,
+    body: &[u8],
+    behavior: Behavior,
+) -> Response<std::io::Cursor<Vec<u8>>> {
+    let header = |name: &str, value: String| Header::from_bytes(name, value).expect("header");
+    let total = body.len() as u64;
+    if *request.method() == Method::Head && behavior.refuse_head {
+        return Response::from_string("HEAD not allowed").with_status_code(403);
+    }
+    let range = request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Range"))
+        .and_then(|h| parse_range(h.value.as_str(), total))
+        .filter(|_| behavior.ranges);
+    let (status, first, last) = match range {
+        Some((first, last)) => (206, first, last),
+        None => (200, 0, total.saturating_sub(1)),
+    };
+    let data = if *request.method() == Method::Head {
+        Vec::new()
+    } else {
+        body[first as usize..=last as usize].to_vec()
+    };
+    let mut response = Response::from_data(data)
+        .with_status_code(status)
+        .with_header(header("Content-Length", (last + 1 - first).to_string()));
+    if behavior.ranges {
+        response.add_header(header("Accept-Ranges", "bytes".into()));
+    }
+    if status == 206 {
+        response.add_header(header(
+            "Content-Range",
+            format!("bytes {}-{}/{}", first, last, total),
+        ));
+    }
+    response
+}
+
+/// `bytes=first-last` or `bytes=first-`, clamped to the body.
+fn parse_range(value: &str, total: u64) -> Option<(u64, u64)> {
+    let (first, last) = value.trim().strip_prefix("bytes=")?.split_once('-')?;
+    let first = first.trim().parse::<u64>().ok()?;
+    let last = match last.trim() {
+        "" => total - 1,
+        n => n.parse::<u64>().ok()?.min(total - 1),
+    };
+    (first <= last).then_some((first, last))
+}
+
+/// Deterministic, non-repeating-looking test content.
+pub fn body(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 31 % 251) as u8).collect()
+}
+
+/// An empty directory for one test's downloads.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir =
+        std::env::temp_dir()
+            .join("adm-tests")
+            .join(format!("{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    dir
+}
+
+/// App state whose settings, queue and sidecars live in a scratch directory
+/// shared by the tests instead of the user's.
+pub fn state() -> AppState {
+    static ROOT: OnceLock<PathBuf> = OnceLock::new();
+    let root = ROOT.get_or_init(|| temp_dir("data"));
+    AppState::new(AppDirs {
+        data: Some(root.join("data")),
+        config: Some(root.join("config")),
+    })
+}
+
+struct Silent;
+
+impl DownloadEvents for Silent {}
+
+pub fn events() -> Events {
+    Arc::new(Silent)
+}
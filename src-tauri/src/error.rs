@@ -0,0 +1,157 @@
+use std::fmt;
+use std::io;
+
+use serde::Serialize;
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// Connection refused, reset or timed out.
+    Network,
+    Dns,
+    Tls,
+    HttpStatus,
+    DiskFull,
+    PermissionDenied,
+    /// Any other local file error.
+    Io,
+    ChecksumMismatch,
+    /// The remote file is no longer the one the partial download came from.
+    ServerChanged,
+    Canceled,
+    /// Not a failure: the download stopped on request and can be resumed.
+    Paused,
+    Other,
+}
+
+/// Why a download stopped. Serialized as `{ kind, retryable, message, details? }`
+/// in `download_failed` events and command errors; `retryable` tells clients
+/// whether trying again later can help.
+#[derive(Serialize, Clone, Debug)]
+pub struct DownloadError {
+    pub kind: ErrorKind,
+    pub retryable: bool,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
+}
+
+impl DownloadError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            retryable: matches!(kind, ErrorKind::Network | ErrorKind::Dns),
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    /// A failed request; `context` names the request, e.g. `"HEAD"`.
+    pub fn request(context: &str, e: reqwest::Error) -> Self {
+        if let Some(status) = e.status() {
+            return Self::status(status.as_u16());
+        }
+        let mut chain = e.to_string();
+        let mut source = std::error::Error::source(&e);
+        while let Some(inner) = source {
+            chain.push_str(": ");
+            chain.push_str(&inner.to_string());
+            source = inner.source();
+        }
+        let lower = chain.to_lowercase();
+        let kind = if [
+            "dns error",
+            "failed to lookup address",
+            "name or service not known",
+            "no such host",
+        ]
+        .iter()
+        .any(|s| lower.contains(s))
+        {
+            ErrorKind::Dns
+        } else if ["certificate", "tls", "ssl", "handshake"]
+            .iter()
+            .any(|s| lower.contains(s))
+        {
+            ErrorKind::Tls
+        } else {
+            ErrorKind::Network
+        };
+        Self::new(kind, format!("{} error: {}", context, chain))
+    }
+
+    /// A local file operation failed; `context` names it, e.g. `"Write"`.
+    pub fn io(context: &str, e: io::Error) -> Self {
+        let kind = match e.kind() {
+            io::ErrorKind::StorageFull | io::ErrorKind::QuotaExceeded => ErrorKind::DiskFull,
+            io::ErrorKind::PermissionDenied | io::ErrorKind::ReadOnlyFilesystem => {
+                ErrorKind::PermissionDenied
+            }
+            _ => ErrorKind::Io,
+        };
+        Self::new(kind, format!("{} error: {}", context, e))
+    }
+
+    /// The server answered with a status other than the one expected. Server
+    /// errors, timeouts and rate limiting are worth retrying.
+    pub fn status(code: u16) -> Self {
+        let mut error = Self::new(
+            ErrorKind::HttpStatus,
+            format!("Unexpected status: {}", code),
+        );
+        error.retryable = matches!(code, 408 | 429 | 500..=599);
+        error.details = Some(serde_json::json!({ "status": code }));
+        error
+    }
+
+    pub fn checksum_mismatch(algorithm: &str, expected: &str, actual: &str) -> Self {
+        let mut error = Self::new(
+            ErrorKind::ChecksumMismatch,
+            format!(
+                "checksum mismatch ({}): expected {}, got {}",
+                algorithm, expected, actual
+            ),
+        );
+        error.details = Some(serde_json::json!({
+            "algorithm": algorithm,
+            "expected": expected,
+            "actual": actual,
+        }));
+        error
+    }
+
+    pub fn server_changed(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::ServerChanged, message)
+    }
+
+    pub fn canceled() -> Self {
+        Self::new(ErrorKind::Canceled, "canceled")
+    }
+
+    pub fn paused() -> Self {
+        Self::new(ErrorKind::Paused, "paused")
+    }
+
+    /// Paused and canceled downloads stopped on request rather than failing.
+    pub fn is_failure(&self) -> bool {
+        !matches!(self.kind, ErrorKind::Paused | ErrorKind::Canceled)
+    }
+}
+
+impl fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl From<String> for DownloadError {
+    fn from(message: String) -> Self {
+        Self::new(ErrorKind::Other, message)
+    }
+}
+
+impl From<&str> for DownloadError {
+    fn from(message: &str) -> Self {
+        Self::new(ErrorKind::Other, message)
+    }
+}
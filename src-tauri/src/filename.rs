@@ -0,0 +1,150 @@
+//! Turning untrusted names (user input, `Content-Disposition`, URLs, the
+//! browser bridge, deep links) into a single safe path component.
+
+/// Longest name kept, in UTF-8 bytes. Filesystems allow 255; the rest is room
+/// for the ` (n)` conflict suffix and the temporary file suffix.
+const MAX_NAME_BYTES: usize = 200;
+
+/// Extensions longer than this are treated as part of the name when truncating.
+const MAX_EXTENSION_BYTES: usize = 16;
+
+const FALLBACK_NAME: &str = "download.bin";
+
+/// A file name that stays inside the directory it is joined to: directory
+/// components are dropped, characters the platform rejects become `_`,
+/// leading and trailing dots and spaces are trimmed, Windows device names get
+/// a `_` prefix and long names are cut down keeping their extension.
+pub fn sanitize(name: &str) -> String {
+    // Both separators on every platform: a server may send either
+    let last = name
+        .rsplit(['/', '\\'])
+        .find(|part| !part.trim().is_empty())
+        .unwrap_or_default();
+    let replaced: String = last
+        .chars()
+        .map(|c| if is_illegal(c) { '_' } else { c })
+        .collect();
+    let mut name = trim(&replaced).to_string();
+    if cfg!(windows) && is_reserved(&name) {
+        name.insert(0, '_');
+    }
+    let name = truncate(&name);
+    if name.is_empty() {
+        FALLBACK_NAME.to_string()
+    } else {
+        name
+    }
+}
+
+fn is_illegal(c: char) -> bool {
+    c.is_control()
+        || (cfg!(windows) && matches!(c, '<' | '>' | ':' | '"' | '|' | '?' | '*'))
+        || (cfg!(target_os = "macos") && c == ':')
+}
+
+/// Dotfiles would be hidden, `.`/`..` are directories, and Windows drops
+/// trailing dots and spaces on its own.
+fn trim(name: &str) -> &str {
+    name.trim_matches(|c: char| c == '.' || c.is_whitespace())
+}
+
+/// `CON`, `NUL`, `COM1`... refer to devices on Windows, with any extension.
+fn is_reserved(name: &str) -> bool {
+    let stem = name.split('.').next().unwrap_or_default().trim_end();
+    let upper = stem.to_ascii_uppercase();
+    if matches!(upper.as_str(), "CON" | "PRN" | "AUX" | "NUL") {
+        return true;
+    }
+    let Some(port) = upper
+        .strip_prefix("COM")
+        .or_else(|| upper.strip_prefix("LPT"))
+    else {
+        return false;
+    };
+    let mut chars = port.chars();
+    matches!(
+        (chars.next(), chars.next()),
+        (Some('0'..='9' | '¹' | '²' | '³'), None)
+    )
+}
+
+fn truncate(name: &str) -> String {
+    if name.len() <= MAX_NAME_BYTES {
+        return name.to_string();
+    }
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() && ext.len() <= MAX_EXTENSION_BYTES => {
+            (stem, Some(ext))
+        }
+        _ => (name, None),
+    };
+    let budget = MAX_NAME_BYTES - extension.map_or(0, |ext| ext.len() + 1);
+    let mut end = budget.min(stem.len());
+    while !stem.is_char_boundary(end) {
+        end -= 1;
+    }
+    let stem = trim(&stem[..end]);
+    match extension {
+        Some(ext) => format!("{}.{}", stem, ext),
+        None => stem.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_names_inside_the_directory() {
+        let cases = [
+            ("report.pdf", "report.pdf"),
+            ("../../.bashrc", "bashrc"),
+            ("..", FALLBACK_NAME),
+            ("a/..", FALLBACK_NAME),
+            ("a/b/", "b"),
+            ("\\\\server\\share\\x", "x"),
+            ("a\0b\x1f.zip", "a_b_.zip"),
+            ("", FALLBACK_NAME),
+            ("  . ", FALLBACK_NAME),
+            ("name.txt. . ", "name.txt"),
+        ];
+        for (name, expected) in cases {
+            assert_eq!(sanitize(name), expected, "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn recognises_windows_device_names() {
+        for name in [
+            "con.txt",
+            "CON",
+            "nul.tar.gz",
+            "COM1",
+            "COM¹",
+            "lpt9.log",
+            "aux .txt",
+        ] {
+            assert!(is_reserved(name), "{:?}", name);
+        }
+        for name in ["console.txt", "COM10", "com", "LPT0x", "icon.png"] {
+            assert!(!is_reserved(name), "{:?}", name);
+        }
+        if cfg!(windows) {
+            assert_eq!(sanitize("con.txt"), "_con.txt");
+            assert_eq!(sanitize("COM¹"), "_COM¹");
+        }
+    }
+
+    #[test]
+    fn truncates_on_a_char_boundary_keeping_the_extension() {
+        // 2-byte characters after one ASCII byte, so the byte budget falls mid-character
+        let name = format!("a{}.zip", "é".repeat(150));
+        let expected = format!("a{}.zip", "é".repeat(97));
+        assert_eq!(sanitize(&name), expected);
+        assert!(expected.len() <= MAX_NAME_BYTES);
+
+        // An overlong "extension" is just part of the name
+        let name = format!("{}.{}", "a".repeat(300), "b".repeat(20));
+        assert_eq!(sanitize(&name), "a".repeat(MAX_NAME_BYTES));
+    }
+}
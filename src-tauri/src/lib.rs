@@ -1,10 +1,42 @@
 // New modularized structure
+// Without the app, the parts only its commands and the bridge use are idle
+#![cfg_attr(not(feature = "app"), allow(dead_code))]
+
+#[cfg(feature = "app")]
+mod api;
+mod checksum;
+pub mod cli;
+#[cfg(feature = "app")]
 pub mod commands;
+#[cfg(feature = "app")]
+mod deeplink;
+mod engine;
+mod error;
+mod filename;
+mod limiter;
+mod pairing;
 mod payloads;
+mod queue;
+mod registry;
+mod request;
+mod retry;
+mod settings;
+mod sidecar;
+#[cfg(feature = "app")]
+mod sse;
 mod state;
 mod util;
+// Keeps its own 2-space style
+#[cfg(feature = "app")]
+#[rustfmt::skip]
 mod server;
 
+#[cfg(feature = "app")]
+use tauri::Manager;
+#[cfg(feature = "app")]
+use tauri_plugin_deep_link::DeepLinkExt;
+
+#[cfg(feature = "app")]
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     let mut builder = tauri::Builder::default()
@@ -12,16 +44,43 @@ pub fn run() {
         .manage(crate::state::AppState::default())
         .invoke_handler(tauri::generate_handler![
             crate::commands::http::start_download,
+            crate::commands::http::pause_download,
+            crate::commands::http::resume_download,
             crate::commands::core::probe_url,
+            crate::commands::core::list_downloads,
+            crate::commands::core::cancel_download,
+            crate::commands::core::resolve_conflict,
             crate::commands::core::delete_download,
+            crate::commands::core::set_speed_limit,
+            crate::commands::core::verify_file,
             crate::commands::manic::start_download_manic,
+            crate::commands::queue::enqueue_download,
+            crate::commands::queue::list_queue,
+            crate::commands::queue::reorder_queue,
+            crate::commands::queue::move_to_top,
+            crate::commands::queue::remove_from_queue,
+            crate::commands::queue::import_links,
+            crate::commands::settings::get_settings,
+            crate::commands::settings::update_settings,
+            crate::commands::bridge::get_bridge_pairing,
+            crate::commands::bridge::regenerate_bridge_token,
+            crate::commands::bridge::allow_bridge_extension,
+            crate::commands::bridge::revoke_bridge_extension,
+            crate::commands::bridge::bridge_status,
+            crate::commands::bridge::take_pending_adds,
         ]);
 
     #[cfg(desktop)]
     {
-        builder = builder.plugin(tauri_plugin_single_instance::init(|_app, argv, _cwd| {
-            println!("a new app instance was opened with {argv:?} and the deep link event was already triggered");
-            // when defining deep link schemes at runtime, also check `argv` here
+        builder = builder.plugin(tauri_plugin_single_instance::init(|app, argv, _cwd| {
+            // adm:// links in `argv` already reached the deep-link handler
+            for payload in crate::deeplink::urls_from_args(&argv) {
+                crate::deeplink::dispatch(app, payload);
+            }
+            if let Some(window) = app.get_webview_window("main") {
+                let _ = window.unminimize();
+                let _ = window.set_focus();
+            }
         }));
     }
 
@@ -30,6 +89,28 @@ pub fn run() {
     builder = builder.setup(|app| {
         // Start localhost HTTP bridge for Chrome extension
         crate::server::start_bridge(app.handle().clone());
+        // Pick up whatever was left in the persisted queue
+        crate::queue::pump(app.handle());
+        // adm:// links, at launch and from later instances
+        let handle = app.handle().clone();
+        app.deep_link().on_open_url(move |event| {
+            for link in event.urls() {
+                if let Some(payload) = crate::deeplink::parse_deep_link(link.as_str()) {
+                    crate::deeplink::dispatch(&handle, payload);
+                }
+            }
+        });
+        if let Ok(Some(links)) = app.deep_link().get_current() {
+            for link in links {
+                if let Some(payload) = crate::deeplink::parse_deep_link(link.as_str()) {
+                    crate::deeplink::dispatch(app.handle(), payload);
+                }
+            }
+        }
+        let args: Vec<String> = std::env::args().collect();
+        for payload in crate::deeplink::urls_from_args(&args) {
+            crate::deeplink::dispatch(app.handle(), payload);
+        }
         Ok(())
     });
 
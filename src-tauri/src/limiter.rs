@@ -0,0 +1,72 @@
+use std::sync::{Arc, Mutex};
+
+use tokio::time::{Duration, Instant};
+
+/// Token bucket limiting throughput to `rate` bytes per second (0 = unlimited).
+/// The bucket holds at most one second worth of tokens, so bursts stay short.
+pub struct RateLimiter {
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    rate: u64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(rate: u64) -> Self {
+        Self {
+            bucket: Mutex::new(Bucket {
+                rate,
+                tokens: rate as f64,
+                last: Instant::now(),
+            }),
+        }
+    }
+
+    /// Change the limit; transfers already waiting pick it up on their next chunk.
+    pub fn set_rate(&self, rate: u64) {
+        if let Ok(mut b) = self.bucket.lock() {
+            b.rate = rate;
+            b.tokens = b.tokens.min(rate as f64);
+            b.last = Instant::now();
+        }
+    }
+
+    /// Take `n` bytes worth of tokens, sleeping off any deficit.
+    pub async fn acquire(&self, n: u64) {
+        let wait = {
+            let Ok(mut b) = self.bucket.lock() else {
+                return;
+            };
+            if b.rate == 0 {
+                return;
+            }
+            let now = Instant::now();
+            let refill = now.duration_since(b.last).as_secs_f64() * b.rate as f64;
+            b.tokens = (b.tokens + refill).min(b.rate as f64);
+            b.last = now;
+            b.tokens -= n as f64;
+            if b.tokens >= 0.0 {
+                return;
+            }
+            Duration::from_secs_f64(-b.tokens / b.rate as f64)
+        };
+        tokio::time::sleep(wait).await;
+    }
+}
+
+/// The limiters a single download has to pass: its own and the global one.
+#[derive(Clone)]
+pub struct Throttle {
+    pub download: Arc<RateLimiter>,
+    pub global: Arc<RateLimiter>,
+}
+
+impl Throttle {
+    pub async fn consume(&self, n: u64) {
+        self.download.acquire(n).await;
+        self.global.acquire(n).await;
+    }
+}
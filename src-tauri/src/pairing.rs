@@ -0,0 +1,89 @@
+use serde::{Deserialize, Serialize};
+
+use crate::util::AppDirs;
+
+/// Browser schemes an extension origin can use; the host part is the extension id.
+const EXTENSION_SCHEMES: [&str; 3] = [
+    "chrome-extension://",
+    "moz-extension://",
+    "safari-web-extension://",
+];
+
+/// Secret the browser extension presents to the localhost bridge, plus the
+/// extension ids whose origins are allowed to call it. Kept out of `Settings`
+/// so the settings UI never round-trips the token.
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct BridgePairing {
+    pub token: String,
+    pub allowed_extensions: Vec<String>,
+}
+
+impl BridgePairing {
+    /// Load the pairing, creating and persisting a fresh token on first run.
+    pub fn load(dirs: &AppDirs) -> Self {
+        let mut pairing: Self = pairing_path(dirs)
+            .and_then(|p| std::fs::read(p).ok())
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+        if pairing.token.is_empty() {
+            pairing.token = new_token();
+            let _ = pairing.save(dirs);
+        }
+        pairing
+    }
+
+    pub fn save(&self, dirs: &AppDirs) -> Result<(), String> {
+        let path =
+            pairing_path(dirs).ok_or_else(|| "Cannot resolve config directory".to_string())?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| format!("Create dir error: {}", e))?;
+        }
+        let json =
+            serde_json::to_vec_pretty(self).map_err(|e| format!("Serialize error: {}", e))?;
+        std::fs::write(&path, json).map_err(|e| format!("Write error: {}", e))
+    }
+
+    /// Replace the token, invalidating every extension paired so far: they are
+    /// also dropped from the allow-list, so each has to be approved again
+    /// before `/pair` hands it the new token.
+    pub fn regenerate(&mut self) -> String {
+        self.token = new_token();
+        self.allowed_extensions.clear();
+        self.token.clone()
+    }
+
+    pub fn check_token(&self, presented: &str) -> bool {
+        !self.token.is_empty() && constant_time_eq(self.token.as_bytes(), presented.as_bytes())
+    }
+
+    pub fn is_allowed_origin(&self, origin: &str) -> bool {
+        extension_id(origin).is_some_and(|id| self.allowed_extensions.iter().any(|a| a == id))
+    }
+}
+
+/// Extension id of an `chrome-extension://<id>`-style origin; `None` for web pages.
+pub fn extension_id(origin: &str) -> Option<&str> {
+    let rest = EXTENSION_SCHEMES
+        .iter()
+        .find_map(|scheme| origin.strip_prefix(scheme))?;
+    let id = rest.trim_end_matches('/');
+    (!id.is_empty() && !id.contains('/')).then_some(id)
+}
+
+fn pairing_path(dirs: &AppDirs) -> Option<std::path::PathBuf> {
+    dirs.config.as_ref().map(|d| d.join("bridge.json"))
+}
+
+fn new_token() -> String {
+    let mut buf = [0u8; 32];
+    if getrandom::getrandom(&mut buf).is_err() {
+        // Not expected on desktop platforms; an empty token rejects every request
+        return String::new();
+    }
+    buf.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
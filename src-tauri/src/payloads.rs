@@ -1,4 +1,25 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+
+use crate::engine::probe::ResourceInfo;
+use crate::error::DownloadError;
+use crate::request::RequestOptions;
+
+/// Everything needed to start a download, as passed to the start commands or
+/// stored in the queue.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct DownloadRequest {
+    pub url: String,
+    pub threads: u8,
+    pub dest_dir: Option<String>,
+    pub file_name: Option<String>,
+    #[serde(default)]
+    pub expected_checksum: Option<String>,
+    #[serde(default)]
+    pub options: RequestOptions,
+    /// A previous `probe_url` result; reused instead of probing again while fresh.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resource: Option<ResourceInfo>,
+}
 
 #[derive(Serialize, Clone)]
 pub struct ProgressPayload {
@@ -6,6 +27,8 @@ pub struct ProgressPayload {
     pub received: u64,
     pub total: u64,
     pub speed: u64,
+    /// Segment requests retried so far after transient errors.
+    pub retries: u32,
 }
 
 #[derive(Serialize, Clone)]
@@ -23,10 +46,21 @@ pub struct CompletedPayload {
     pub path: String,
 }
 
+/// The file a download would create already exists; answer with `resolve_conflict`.
+#[derive(Serialize, Clone)]
+pub struct ConflictPayload {
+    pub id: String,
+    pub path: String,
+    /// Size of the existing file.
+    pub size: u64,
+    /// Size of the download, when known.
+    pub total: Option<u64>,
+}
+
 #[derive(Serialize, Clone)]
 pub struct FailedPayload {
     pub id: String,
-    pub error: String,
+    pub error: DownloadError,
 }
 
 #[derive(Serialize, Clone)]
@@ -35,9 +69,69 @@ pub struct CanceledPayload {
 }
 
 #[derive(Serialize, Clone)]
-pub struct ProbeResult {
+pub struct PausedPayload {
+    pub id: String,
+    pub received: u64,
     pub total: Option<u64>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct ResumedPayload {
+    pub id: String,
+    pub url: String,
     pub file_name: String,
+    pub dest_dir: String,
+    pub total: Option<u64>,
+    pub received: u64,
+}
+
+#[derive(Serialize, Clone)]
+pub struct VerifyingPayload {
+    pub id: String,
+    pub algorithm: String,
+    pub processed: u64,
+    pub total: u64,
+}
+
+/// A link handed over by the browser extension, with the browser context needed
+/// to fetch it; the add window passes `options` back to `enqueue_download`.
+#[derive(Serialize, Clone)]
+pub struct BridgeAddPayload {
+    pub url: String,
+    pub file_name: Option<String>,
+    pub dest_dir: Option<String>,
+    pub options: RequestOptions,
+}
+
+/// Outcome of adding many links at once: ids of the new queue items and the
+/// links skipped because they were already known.
+#[derive(Serialize, Clone, Default)]
+pub struct BatchResult {
+    pub added: Vec<String>,
+    pub duplicates: Vec<String>,
+}
+
+/// Whether the localhost bridge is listening, and where. The same data (minus
+/// `error`) is written to the discovery file for the extension and CLI.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct BridgeStatus {
+    pub running: bool,
+    pub address: String,
+    pub port: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// An extension asked to pair with the bridge and waits for the user's approval.
+#[derive(Serialize, Clone)]
+pub struct PairRequestPayload {
+    pub extension_id: String,
+}
+
+#[derive(Serialize, Clone)]
+pub struct ProbeResult {
+    #[serde(flatten)]
+    pub resource: ResourceInfo,
     pub category: String,
     pub download_dir: String,
 }
@@ -0,0 +1,255 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "app")]
+use tauri::{AppHandle, Emitter, Manager};
+
+#[cfg(feature = "app")]
+use crate::payloads::BatchResult;
+use crate::payloads::DownloadRequest;
+#[cfg(feature = "app")]
+use crate::state::AppState;
+use crate::util::AppDirs;
+#[cfg(feature = "app")]
+use crate::util::new_download_id;
+
+/// Connections per download for links added in bulk, matching the add window.
+pub const DEFAULT_THREADS: u8 = 4;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Engine {
+    Http,
+    Manic,
+}
+
+/// A download waiting for a free slot. Higher `priority` runs first; items with
+/// the same priority keep their insertion order.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct QueueItem {
+    pub id: String,
+    #[serde(flatten)]
+    pub request: DownloadRequest,
+    pub engine: Engine,
+    pub priority: i32,
+}
+
+#[derive(Default)]
+pub struct DownloadQueue {
+    pub items: Vec<QueueItem>,
+    /// Ids launched by the queue that have not finished yet.
+    pub launched: HashSet<String>,
+}
+
+impl DownloadQueue {
+    pub fn load(dirs: &AppDirs) -> Self {
+        let items = queue_path(dirs)
+            .and_then(|p| std::fs::read(p).ok())
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+        Self {
+            items,
+            launched: HashSet::new(),
+        }
+    }
+
+    pub fn save(&self, dirs: &AppDirs) -> Result<(), String> {
+        let path = queue_path(dirs).ok_or_else(|| "Cannot resolve data directory".to_string())?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| format!("Create dir error: {}", e))?;
+        }
+        let json = serde_json::to_vec_pretty(&self.items)
+            .map_err(|e| format!("Serialize error: {}", e))?;
+        std::fs::write(&path, json).map_err(|e| format!("Write error: {}", e))
+    }
+
+    pub fn push(&mut self, item: QueueItem) {
+        let pos = self
+            .items
+            .iter()
+            .position(|i| i.priority < item.priority)
+            .unwrap_or(self.items.len());
+        self.items.insert(pos, item);
+    }
+
+    pub fn remove(&mut self, id: &str) -> Option<QueueItem> {
+        let pos = self.items.iter().position(|i| i.id == id)?;
+        Some(self.items.remove(pos))
+    }
+
+    /// Move an item to `position`, adopting the priority of its new neighbours
+    /// so that later insertions keep the manual order intact.
+    pub fn reorder(&mut self, id: &str, position: usize) -> Result<(), String> {
+        let mut item = self
+            .remove(id)
+            .ok_or_else(|| format!("No queued download with id {}", id))?;
+        let pos = position.min(self.items.len());
+        let upper = if pos > 0 {
+            Some(self.items[pos - 1].priority)
+        } else {
+            None
+        };
+        let lower = self.items.get(pos).map(|i| i.priority);
+        item.priority = match (upper, lower) {
+            (Some(u), _) if item.priority > u => u,
+            (_, Some(l)) if item.priority < l => l,
+            _ => item.priority,
+        };
+        self.items.insert(pos, item);
+        Ok(())
+    }
+}
+
+fn queue_path(dirs: &AppDirs) -> Option<std::path::PathBuf> {
+    dirs.data.as_ref().map(|d| d.join("queue.json"))
+}
+
+/// Key used to recognise the same link twice; the fragment never reaches the server.
+fn dedup_key(url: &str) -> &str {
+    url.trim().split('#').next().unwrap_or_default()
+}
+
+/// `dest_dir` (or the system download folder) with the category folder appended,
+/// the same layout the add window builds. The category comes from outside (the
+/// bridge, deep links) so it is sanitized into a single folder name.
+pub fn batch_dest_dir(dest_dir: Option<String>, category: Option<String>) -> Option<String> {
+    let category = category.filter(|c| !c.trim().is_empty());
+    let base = match dest_dir {
+        Some(dir) => std::path::PathBuf::from(dir),
+        None if category.is_some() => dirs::download_dir().or_else(dirs::home_dir)?,
+        None => return None,
+    };
+    let dir = match category {
+        Some(category) => base.join(crate::filename::sanitize(&category)),
+        None => base,
+    };
+    Some(dir.to_string_lossy().to_string())
+}
+
+/// Queue many downloads at once, skipping links that are already queued,
+/// running or paused, and repeats within the batch itself.
+#[cfg(feature = "app")]
+pub fn enqueue_batch(
+    app: &AppHandle,
+    requests: Vec<DownloadRequest>,
+) -> Result<BatchResult, String> {
+    let state = app.state::<AppState>();
+    let paused = crate::sidecar::load_all(&state.dirs);
+    let mut known: HashSet<String> = paused
+        .iter()
+        .map(|s| dedup_key(&s.url).to_string())
+        .collect();
+    {
+        let downloads = state.downloads.lock().map_err(|_| "State poisoned")?;
+        known.extend(
+            downloads
+                .unfinished_urls()
+                .iter()
+                .map(|u| dedup_key(u).to_string()),
+        );
+    }
+    let mut result = BatchResult::default();
+    {
+        let mut queue = state.queue.lock().map_err(|_| "State poisoned")?;
+        known.extend(
+            queue
+                .items
+                .iter()
+                .map(|i| dedup_key(&i.request.url).to_string()),
+        );
+        for request in requests {
+            if !known.insert(dedup_key(&request.url).to_string()) {
+                result.duplicates.push(request.url);
+                continue;
+            }
+            let id = new_download_id();
+            queue.push(QueueItem {
+                id: id.clone(),
+                request,
+                engine: Engine::Http,
+                priority: 0,
+            });
+            result.added.push(id);
+        }
+        if !result.added.is_empty() {
+            queue.save(&state.dirs)?;
+            notify(app, &queue);
+        }
+    }
+    pump(app);
+    Ok(result)
+}
+
+#[cfg(feature = "app")]
+pub fn notify(app: &AppHandle, queue: &DownloadQueue) {
+    let _ = app.emit("queue_updated", queue.items.clone());
+}
+
+/// Start queued downloads until the active-download limit is reached. Called
+/// on startup, after enqueueing and whenever any download stops.
+#[cfg(feature = "app")]
+pub fn pump(app: &AppHandle) {
+    let state = app.state::<AppState>();
+    let max_active = match state.settings.lock() {
+        Ok(s) => s.max_active_downloads.max(1),
+        Err(_) => return,
+    };
+    let mut started = Vec::new();
+    {
+        let Ok(mut queue) = state.queue.lock() else {
+            return;
+        };
+        let Ok(downloads) = state.downloads.lock() else {
+            return;
+        };
+        let mut active = queue.launched.len()
+            + downloads
+                .running_ids()
+                .iter()
+                .filter(|id| !queue.launched.contains(*id))
+                .count();
+        drop(downloads);
+        while active < max_active && !queue.items.is_empty() {
+            let item = queue.items.remove(0);
+            queue.launched.insert(item.id.clone());
+            started.push(item);
+            active += 1;
+        }
+        if started.is_empty() {
+            return;
+        }
+        let _ = queue.save(&state.dirs);
+        notify(app, &queue);
+    }
+    for item in started {
+        let app = app.clone();
+        tauri::async_runtime::spawn(async move {
+            let state = app.state::<AppState>();
+            let events = crate::commands::events::app_events(&app);
+            let _ = match item.engine {
+                Engine::Http => {
+                    crate::engine::http::run_download(
+                        &events,
+                        &state,
+                        Some(item.id.clone()),
+                        item.request,
+                    )
+                    .await
+                }
+                Engine::Manic => {
+                    crate::engine::manic::run_download_manic(
+                        &events,
+                        &state,
+                        Some(item.id.clone()),
+                        item.request,
+                    )
+                    .await
+                }
+            };
+            if let Ok(mut queue) = state.queue.lock() {
+                queue.launched.remove(&item.id);
+            }
+            pump(&app);
+        });
+    }
+}
@@ -0,0 +1,364 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use serde::Serialize;
+
+use crate::error::{DownloadError, ErrorKind};
+use crate::state::{AppState, DownloadMeta};
+use crate::util::{AppDirs, now_ms, split_dest};
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum DownloadStatus {
+    Queued,
+    Probing,
+    Downloading,
+    Paused,
+    Verifying,
+    Completed,
+    Failed,
+    Canceled,
+}
+
+impl DownloadStatus {
+    /// A transfer task owns the download and will report how it ended.
+    pub fn is_running(self) -> bool {
+        matches!(self, Self::Probing | Self::Downloading | Self::Verifying)
+    }
+
+    pub fn is_final(self) -> bool {
+        matches!(self, Self::Completed | Self::Failed | Self::Canceled)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Queued => "queued",
+            Self::Probing => "probing",
+            Self::Downloading => "downloading",
+            Self::Paused => "paused",
+            Self::Verifying => "verifying",
+            Self::Completed => "completed",
+            Self::Failed => "failed",
+            Self::Canceled => "canceled",
+        }
+    }
+
+    /// Paused, failed and canceled downloads keep their sidecar and may be
+    /// started again; a completed one may not.
+    fn can_become(self, next: Self) -> bool {
+        use DownloadStatus::*;
+        matches!(
+            (self, next),
+            (Queued, Probing | Canceled)
+                | (Probing, Downloading | Failed | Canceled)
+                | (
+                    Downloading,
+                    Paused | Verifying | Completed | Failed | Canceled
+                )
+                | (Verifying, Completed | Failed)
+                | (Paused, Probing | Canceled)
+                | (Failed | Canceled, Probing)
+        )
+    }
+}
+
+/// What the UI and the REST API see of one download.
+#[derive(Serialize, Clone)]
+pub struct DownloadRecord {
+    pub id: String,
+    pub url: String,
+    pub file_name: String,
+    pub dest_dir: String,
+    pub total: Option<u64>,
+    pub received: u64,
+    pub status: DownloadStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<DownloadError>,
+    /// Unix time in milliseconds.
+    pub created_at: u64,
+    pub updated_at: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<u64>,
+}
+
+/// Shared with the transfer task of a running download.
+#[derive(Clone)]
+pub struct DownloadFlags {
+    pub cancel: Arc<AtomicBool>,
+    pub pause: Arc<AtomicBool>,
+    pub received: Arc<AtomicU64>,
+}
+
+struct Entry {
+    record: DownloadRecord,
+    meta: Option<DownloadMeta>,
+    flags: DownloadFlags,
+    pausable: bool,
+}
+
+/// Every download the app knows about in this session, keyed by id. The engine
+/// moves records through [`DownloadStatus`]; commands only read them or raise
+/// the pause/cancel flags.
+#[derive(Default)]
+pub struct Registry {
+    entries: HashMap<String, Entry>,
+}
+
+impl Registry {
+    /// Downloads left paused by an earlier session, from their sidecars.
+    pub fn load(dirs: &AppDirs) -> Self {
+        let mut registry = Self::default();
+        for sidecar in crate::sidecar::load_all(dirs) {
+            let now = now_ms();
+            let meta = DownloadMeta {
+                url: sidecar.url.clone(),
+                dest: sidecar.dest.clone(),
+                temp: sidecar.temp.clone(),
+                total: sidecar.total,
+                accept_ranges: sidecar.accept_ranges,
+                etag: sidecar.etag.clone(),
+                last_modified: sidecar.last_modified.clone(),
+            };
+            let mut record = new_record(&sidecar.id, &sidecar.url, DownloadStatus::Paused, now);
+            describe(&mut record, &meta);
+            record.received = sidecar.received();
+            registry.entries.insert(
+                sidecar.id.clone(),
+                Entry {
+                    record,
+                    meta: Some(meta),
+                    flags: new_flags(sidecar.received()),
+                    pausable: true,
+                },
+            );
+        }
+        registry
+    }
+
+    /// Claim `id` for a new transfer task, which starts out probing the server.
+    pub fn begin(&mut self, id: &str, url: &str, pausable: bool) -> Result<DownloadFlags, String> {
+        let now = now_ms();
+        let (record, meta, received) = match self.entries.remove(id) {
+            Some(entry) if !entry.record.status.can_become(DownloadStatus::Probing) => {
+                let status = entry.record.status;
+                self.entries.insert(id.to_string(), entry);
+                return Err(if status.is_running() {
+                    "Download is already running".into()
+                } else {
+                    format!("Download is {}", status.as_str())
+                });
+            }
+            Some(entry) => {
+                let mut record = entry.record;
+                record.status = DownloadStatus::Probing;
+                record.error = None;
+                record.finished_at = None;
+                record.updated_at = now;
+                (
+                    record,
+                    entry.meta,
+                    entry.flags.received.load(Ordering::Relaxed),
+                )
+            }
+            None => (new_record(id, url, DownloadStatus::Probing, now), None, 0),
+        };
+        let flags = new_flags(received);
+        self.entries.insert(
+            id.to_string(),
+            Entry {
+                record,
+                meta,
+                flags: flags.clone(),
+                pausable,
+            },
+        );
+        Ok(flags)
+    }
+
+    /// The probe is done and bytes are about to be transferred to `meta.temp`.
+    pub fn downloading(&mut self, id: &str, meta: DownloadMeta) -> Result<(), String> {
+        let entry = self.entry(id)?;
+        describe(&mut entry.record, &meta);
+        entry.meta = Some(meta);
+        transition(&mut entry.record, DownloadStatus::Downloading)
+    }
+
+    pub fn verifying(&mut self, id: &str) -> Result<(), String> {
+        transition(&mut self.entry(id)?.record, DownloadStatus::Verifying)
+    }
+
+    /// Record how a transfer task ended.
+    pub fn finish(&mut self, id: &str, result: &Result<String, DownloadError>) {
+        let Ok(entry) = self.entry(id) else {
+            return;
+        };
+        let record = &mut entry.record;
+        record.received = entry.flags.received.load(Ordering::Relaxed);
+        let next = match result {
+            Ok(_) => DownloadStatus::Completed,
+            Err(e) if e.kind == ErrorKind::Paused => DownloadStatus::Paused,
+            Err(e) if e.kind == ErrorKind::Canceled => DownloadStatus::Canceled,
+            Err(e) => {
+                record.error = Some(e.clone());
+                DownloadStatus::Failed
+            }
+        };
+        let _ = transition(record, next);
+    }
+
+    pub fn pause(&self, id: &str) -> Result<(), String> {
+        let entry = self.running(id)?;
+        if !entry.pausable {
+            return Err("This download can't be paused".into());
+        }
+        entry.flags.pause.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Stop a running download, or give up on a paused one.
+    pub fn cancel(&mut self, id: &str) -> Result<(), String> {
+        let entry = self.entry(id)?;
+        if entry.record.status == DownloadStatus::Paused {
+            return transition(&mut entry.record, DownloadStatus::Canceled);
+        }
+        self.running(id)?
+            .flags
+            .cancel
+            .store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Forget `id`, stopping it first if it is running. Returns its files when
+    /// they are partial downloads that should be removed too.
+    pub fn remove(&mut self, id: &str) -> Option<DownloadMeta> {
+        let entry = self.entries.remove(id)?;
+        entry.flags.cancel.store(true, Ordering::Relaxed);
+        entry
+            .meta
+            .filter(|_| entry.record.status != DownloadStatus::Completed)
+    }
+
+    /// Where `id` is saved, once its name has been decided.
+    pub fn dest(&self, id: &str) -> Option<std::path::PathBuf> {
+        Some(self.entries.get(id)?.meta.as_ref()?.dest.clone())
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.entries.contains_key(id)
+    }
+
+    pub fn is_running(&self, id: &str) -> bool {
+        self.running(id).is_ok()
+    }
+
+    pub fn running_ids(&self) -> Vec<String> {
+        self.entries
+            .values()
+            .filter(|e| e.record.status.is_running())
+            .map(|e| e.record.id.clone())
+            .collect()
+    }
+
+    /// URLs of downloads that are running or can still be resumed.
+    pub fn unfinished_urls(&self) -> Vec<String> {
+        self.entries
+            .values()
+            .filter(|e| !e.record.status.is_final())
+            .map(|e| e.record.url.clone())
+            .collect()
+    }
+
+    pub fn records(&self) -> Vec<DownloadRecord> {
+        let mut records: Vec<DownloadRecord> = self
+            .entries
+            .values()
+            .map(|e| {
+                let mut record = e.record.clone();
+                record.received = e.flags.received.load(Ordering::Relaxed);
+                record
+            })
+            .collect();
+        records.sort_by_key(|r| r.created_at);
+        records
+    }
+
+    fn entry(&mut self, id: &str) -> Result<&mut Entry, String> {
+        self.entries
+            .get_mut(id)
+            .ok_or_else(|| "Unknown download".to_string())
+    }
+
+    fn running(&self, id: &str) -> Result<&Entry, String> {
+        self.entries
+            .get(id)
+            .filter(|e| e.record.status.is_running())
+            .ok_or_else(|| "Download is not running".to_string())
+    }
+}
+
+/// Queued downloads followed by everything in the registry, oldest first.
+pub fn list(state: &AppState) -> Result<Vec<DownloadRecord>, String> {
+    let mut out: Vec<DownloadRecord> = {
+        let queue = state.queue.lock().map_err(|_| "State poisoned")?;
+        queue
+            .items
+            .iter()
+            .map(|item| {
+                let mut record = new_record(&item.id, &item.request.url, DownloadStatus::Queued, 0);
+                record.file_name = item.request.file_name.clone().unwrap_or_default();
+                record.dest_dir = item.request.dest_dir.clone().unwrap_or_default();
+                record
+            })
+            .collect()
+    };
+    let downloads = state.downloads.lock().map_err(|_| "State poisoned")?;
+    out.extend(downloads.records());
+    Ok(out)
+}
+
+fn transition(record: &mut DownloadRecord, next: DownloadStatus) -> Result<(), String> {
+    if !record.status.can_become(next) {
+        return Err(format!(
+            "Download can't go from {} to {}",
+            record.status.as_str(),
+            next.as_str()
+        ));
+    }
+    let now = now_ms();
+    record.status = next;
+    record.updated_at = now;
+    if next.is_final() {
+        record.finished_at = Some(now);
+    }
+    Ok(())
+}
+
+fn describe(record: &mut DownloadRecord, meta: &DownloadMeta) {
+    (record.file_name, record.dest_dir) = split_dest(&meta.dest);
+    record.total = meta.total;
+}
+
+fn new_record(id: &str, url: &str, status: DownloadStatus, now: u64) -> DownloadRecord {
+    DownloadRecord {
+        id: id.to_string(),
+        url: url.to_string(),
+        file_name: String::new(),
+        dest_dir: String::new(),
+        total: None,
+        received: 0,
+        status,
+        error: None,
+        created_at: now,
+        updated_at: now,
+        finished_at: None,
+    }
+}
+
+fn new_flags(received: u64) -> DownloadFlags {
+    DownloadFlags {
+        cancel: Arc::new(AtomicBool::new(false)),
+        pause: Arc::new(AtomicBool::new(false)),
+        received: Arc::new(AtomicU64::new(received)),
+    }
+}
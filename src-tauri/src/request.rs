@@ -0,0 +1,71 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use reqwest::header::{CONTENT_TYPE, COOKIE, REFERER};
+use reqwest::{Client, RequestBuilder};
+use serde::{Deserialize, Serialize};
+
+/// A connection that can't be set up, or goes quiet, this long errors out and
+/// gets retried instead of hanging forever.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+const READ_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Headers the engine sets itself; a forwarded copy would corrupt range requests.
+const RESERVED_HEADERS: [&str; 5] = ["range", "if-range", "host", "content-length", "cookie"];
+
+/// Browser context forwarded with a link so session-bound downloads work:
+/// cookie-protected files, signed CDN links that check `Referer`, form posts.
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct RequestOptions {
+    pub cookies: Option<String>,
+    pub referrer: Option<String>,
+    pub user_agent: Option<String>,
+    pub headers: BTreeMap<String, String>,
+    /// `application/x-www-form-urlencoded` body; when set the file is fetched with POST.
+    pub post_data: Option<String>,
+}
+
+impl RequestOptions {
+    pub fn client(&self) -> Result<Client, String> {
+        let mut builder = Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .read_timeout(READ_TIMEOUT);
+        if let Some(user_agent) = &self.user_agent {
+            builder = builder.user_agent(user_agent.as_str());
+        }
+        builder
+            .build()
+            .map_err(|e| format!("HTTP client error: {}", e))
+    }
+
+    pub fn head(&self, client: &Client, url: &str) -> RequestBuilder {
+        self.apply(client.head(url))
+    }
+
+    /// Request for the file body: GET, or POST carrying the form data.
+    pub fn get(&self, client: &Client, url: &str) -> RequestBuilder {
+        match &self.post_data {
+            Some(body) => self
+                .apply(client.post(url))
+                .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .body(body.clone()),
+            None => self.apply(client.get(url)),
+        }
+    }
+
+    fn apply(&self, mut req: RequestBuilder) -> RequestBuilder {
+        for (name, value) in &self.headers {
+            if !RESERVED_HEADERS.contains(&name.to_ascii_lowercase().as_str()) {
+                req = req.header(name.as_str(), value.as_str());
+            }
+        }
+        if let Some(cookies) = &self.cookies {
+            req = req.header(COOKIE, cookies.as_str());
+        }
+        if let Some(referrer) = &self.referrer {
+            req = req.header(REFERER, referrer.as_str());
+        }
+        req
+    }
+}
@@ -0,0 +1,124 @@
+use std::time::{Duration, SystemTime};
+
+use reqwest::StatusCode;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+
+use crate::error::DownloadError;
+
+/// How a failed segment request is retried.
+#[derive(Clone, Copy)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Exponential backoff with "equal jitter": half of the capped delay is
+    /// fixed, the other half is random so parallel segments don't retry in lockstep.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exp = self
+            .base_delay
+            .saturating_mul(1u32 << attempt.saturating_sub(1).min(16));
+        let capped = exp.min(self.max_delay).as_millis() as u64;
+        let half = capped / 2;
+        Duration::from_millis(half + jitter(half))
+    }
+
+    /// How long to wait before retry number `attempt`: the server's
+    /// `Retry-After` when it sent one, capped at `max_delay` so a far-off date
+    /// can't park a segment for hours, else the backoff.
+    pub fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        match retry_after {
+            Some(wait) => wait.min(self.max_delay),
+            None => self.backoff(attempt),
+        }
+    }
+}
+
+fn jitter(max: u64) -> u64 {
+    if max == 0 {
+        return 0;
+    }
+    let nanos = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .subsec_nanos() as u64;
+    // Scramble the clock so tasks woken together still spread out
+    nanos.wrapping_mul(0x9E37_79B9_7F4A_7C15) % (max + 1)
+}
+
+/// Why a segment attempt stopped early and whether trying again can help.
+pub struct SegmentFailure {
+    pub error: DownloadError,
+    pub retryable: bool,
+    pub retry_after: Option<Duration>,
+}
+
+impl SegmentFailure {
+    pub fn fatal(error: DownloadError) -> Self {
+        Self {
+            error,
+            retryable: false,
+            retry_after: None,
+        }
+    }
+
+    pub fn from_status(status: StatusCode, headers: &HeaderMap) -> Self {
+        let error = DownloadError::status(status.as_u16());
+        let retry_after = if status == StatusCode::TOO_MANY_REQUESTS
+            || status == StatusCode::SERVICE_UNAVAILABLE
+        {
+            parse_retry_after(headers)
+        } else {
+            None
+        };
+        Self {
+            retryable: error.retryable,
+            error,
+            retry_after,
+        }
+    }
+}
+
+/// Retried when the error's kind is transient (network trouble, DNS).
+impl From<DownloadError> for SegmentFailure {
+    fn from(error: DownloadError) -> Self {
+        Self {
+            retryable: error.retryable,
+            error,
+            retry_after: None,
+        }
+    }
+}
+
+/// `Retry-After` is either a number of seconds or an HTTP date.
+fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let at = httpdate::parse_http_date(value).ok()?;
+    Some(at.duration_since(SystemTime::now()).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_after_is_capped_at_max_delay() {
+        let policy = RetryPolicy {
+            max_retries: 5,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+        };
+        let day = Some(Duration::from_secs(86_400));
+        assert_eq!(policy.delay(1, day), Duration::from_secs(30));
+        assert_eq!(
+            policy.delay(1, Some(Duration::from_secs(5))),
+            Duration::from_secs(5)
+        );
+        assert!(policy.delay(1, None) <= Duration::from_secs(1));
+    }
+}
@@ -1,17 +1,46 @@
+use std::io::Read;
 use std::thread;
-use tauri::{AppHandle, Emitter};
-use tiny_http::{Header, Method, Response, Server, StatusCode};
+use tauri::{AppHandle, Emitter, Manager};
+use tiny_http::{Header, Method, Request, Response, Server, StatusCode};
 use serde::Deserialize;
 
+use crate::payloads::{BridgeAddPayload, BridgeStatus, DownloadRequest, PairRequestPayload};
+use crate::request::RequestOptions;
+use crate::state::AppState;
+use crate::util::AppDirs;
+
+/// Body of `/add`: the link plus whatever the extension knows about the
+/// browser session (`cookies`, `referrer`, `user_agent`, `headers`, `post_data`).
 #[derive(Deserialize)]
 struct AddReq {
   url: String,
+  #[serde(default, alias = "filename")]
+  file_name: Option<String>,
+  #[serde(default)]
+  dest_dir: Option<String>,
+  #[serde(flatten)]
+  options: RequestOptions,
+}
+
+/// Body of `/add-batch`: many links queued directly, without the add window.
+/// The shared folder and category apply to entries that don't name their own folder.
+#[derive(Deserialize)]
+struct AddBatchReq {
+  entries: Vec<AddReq>,
+  #[serde(default)]
+  dest_dir: Option<String>,
+  #[serde(default)]
+  category: Option<String>,
 }
 
+/// How many ports after the configured one are tried before giving up.
+const PORT_ATTEMPTS: u16 = 20;
+
 pub fn start_bridge(app: AppHandle) {
+  let events = crate::sse::EventHub::attach(&app);
   // Spawn a lightweight thread HTTP server on localhost
   thread::spawn(move || {
-    let server = match Server::http("127.0.0.1:47891") {
+    let server = match bind(&app) {
       Ok(s) => s,
       Err(e) => {
         eprintln!("ADM bridge server failed to bind: {e}");
@@ -22,31 +51,62 @@ pub fn start_bridge(app: AppHandle) {
     for mut req in server.incoming_requests() {
       let method = req.method().clone();
       let path = req.url().to_string();
+      let origin = header_value(&req, "Origin");
+      // Only allow-listed extension origins get CORS headers back
+      let cors_origin = origin.clone().filter(|o| origin_allowed(&app, o));
 
       // CORS preflight
-      if method == Method::Options && path == "/add" {
-        let resp = Response::from_string("")
-          .with_status_code(StatusCode(204))
-          .with_header(Header::from_bytes(b"Access-Control-Allow-Origin", b"*").unwrap())
-          .with_header(Header::from_bytes(b"Access-Control-Allow-Headers", b"Content-Type").unwrap())
-          .with_header(Header::from_bytes(b"Access-Control-Allow-Methods", b"POST, OPTIONS").unwrap());
+      if method == Method::Options {
+        let resp = with_cors(Response::from_string("").with_status_code(StatusCode(204)), cors_origin.as_deref());
+        let _ = req.respond(resp);
+        continue;
+      }
+
+      if method == Method::Post && path == "/pair" {
+        let (status, body) = pair(&app, origin.as_deref());
+        let resp = with_cors(json_response(status, body), origin.as_deref().filter(|o| crate::pairing::extension_id(o).is_some()));
+        let _ = req.respond(resp);
+        continue;
+      }
+
+      if let Err((status, message)) = authorize(&app, &req, origin.as_deref()) {
+        let resp = with_cors(Response::from_string(message).with_status_code(StatusCode(status)), cors_origin.as_deref());
         let _ = req.respond(resp);
         continue;
       }
 
+      if method == Method::Get && path.split('?').next() == Some("/api/v1/events") {
+        events.serve(req, cors_origin);
+        continue;
+      }
+
+      if path.starts_with("/api/v1/") {
+        let (status, body) = crate::api::handle(&app, method.as_str(), &path);
+        let _ = req.respond(with_cors(json_response(status, body), cors_origin.as_deref()));
+        continue;
+      }
+
       if method == Method::Post && path == "/add" {
         let mut body = String::new();
         let _ = req.as_reader().read_to_string(&mut body);
-        let parsed: Result<AddReq, _> = serde_json::from_str(&body);
-        if let Ok(p) = parsed {
-          let _ = app.emit("adm-add-from-bridge", p.url);
-        }
-        let resp = Response::from_string("OK")
-          .with_status_code(StatusCode(200))
-          .with_header(Header::from_bytes(b"Access-Control-Allow-Origin", b"*").unwrap())
-          .with_header(Header::from_bytes(b"Access-Control-Allow-Headers", b"Content-Type").unwrap())
-          .with_header(Header::from_bytes(b"Access-Control-Allow-Methods", b"POST, OPTIONS").unwrap());
+        let resp = match serde_json::from_str::<AddReq>(&body) {
+          Ok(p) => {
+            let payload = BridgeAddPayload { url: p.url, file_name: p.file_name, dest_dir: p.dest_dir, options: p.options };
+            let _ = app.emit("adm-add-from-bridge", payload);
+            Response::from_string("OK").with_status_code(StatusCode(200))
+          }
+          Err(e) => Response::from_string(format!("Invalid request: {e}")).with_status_code(StatusCode(400)),
+        };
+        let resp = with_cors(resp, cors_origin.as_deref());
         let _ = req.respond(resp);
+      } else if method == Method::Post && path == "/add-batch" {
+        let mut body = String::new();
+        let _ = req.as_reader().read_to_string(&mut body);
+        let (status, body) = match serde_json::from_str::<AddBatchReq>(&body) {
+          Ok(batch) => add_batch(&app, batch),
+          Err(e) => (400, serde_json::json!({ "error": format!("Invalid request: {e}") }).to_string()),
+        };
+        let _ = req.respond(with_cors(json_response(status, body), cors_origin.as_deref()));
       } else {
         let _ = req.respond(
           Response::from_string("Not Found").with_status_code(StatusCode(404))
@@ -55,3 +115,141 @@ pub fn start_bridge(app: AppHandle) {
     }
   });
 }
+
+/// Bind the configured address, falling back to the next free ports, then
+/// record where the bridge ended up in the app state and the discovery file.
+fn bind(app: &AppHandle) -> Result<Server, String> {
+  let state = app.state::<AppState>();
+  let (address, first_port) = {
+    let settings = state.settings.lock().map_err(|_| "State poisoned")?;
+    (settings.bridge_address.clone(), settings.bridge_port)
+  };
+  let mut last_err = String::from("no port to try");
+  for port in (first_port..=u16::MAX).take(PORT_ATTEMPTS as usize) {
+    match Server::http((address.as_str(), port)) {
+      Ok(server) => {
+        let status = BridgeStatus { running: true, address: address.clone(), port: Some(port), error: None };
+        if let Err(e) = write_discovery(&state.dirs, &status) {
+          eprintln!("ADM bridge discovery file not written: {e}");
+        }
+        if let Ok(mut bridge) = state.bridge.lock() {
+          *bridge = status;
+        }
+        return Ok(server);
+      }
+      Err(e) => last_err = format!("{address}:{port}: {e}"),
+    }
+  }
+  if let Ok(mut bridge) = state.bridge.lock() {
+    *bridge = BridgeStatus { running: false, address, port: None, error: Some(last_err.clone()) };
+  }
+  // Don't leave a stale port behind for clients to find
+  if let Some(dir) = &state.dirs.data {
+    let _ = std::fs::remove_file(dir.join("bridge-endpoint.json"));
+  }
+  Err(last_err)
+}
+
+/// `bridge-endpoint.json` in the app data directory tells the extension and CLI
+/// which port the bridge actually bound.
+fn write_discovery(dirs: &AppDirs, status: &BridgeStatus) -> Result<(), String> {
+  let dir = dirs.data.as_ref().ok_or_else(|| "Cannot resolve data directory".to_string())?;
+  std::fs::create_dir_all(dir).map_err(|e| format!("Create dir error: {}", e))?;
+  let json = serde_json::to_vec_pretty(status).map_err(|e| format!("Serialize error: {}", e))?;
+  std::fs::write(dir.join("bridge-endpoint.json"), json).map_err(|e| format!("Write error: {}", e))
+}
+
+fn header_value(req: &Request, name: &str) -> Option<String> {
+  req
+    .headers()
+    .iter()
+    .find(|h| h.field.as_str().as_str().eq_ignore_ascii_case(name))
+    .map(|h| h.value.as_str().to_string())
+}
+
+fn origin_allowed(app: &AppHandle, origin: &str) -> bool {
+  let state = app.state::<AppState>();
+  state.pairing.lock().map(|p| p.is_allowed_origin(origin)).unwrap_or(false)
+}
+
+/// Requests from a browser must come from an allow-listed extension; every
+/// request must carry the pairing token as `X-ADM-Token` or a bearer token.
+/// The event stream also accepts `?token=`, since `EventSource` can't set headers.
+fn authorize(app: &AppHandle, req: &Request, origin: Option<&str>) -> Result<(), (u16, &'static str)> {
+  if origin.is_some_and(|o| !origin_allowed(app, o)) {
+    return Err((403, "Origin not allowed"));
+  }
+  let token = header_value(req, "X-ADM-Token")
+    .or_else(|| {
+      header_value(req, "Authorization")
+        .and_then(|v| v.strip_prefix("Bearer ").map(|t| t.trim().to_string()))
+    })
+    .or_else(|| {
+      req
+        .url()
+        .starts_with("/api/v1/events")
+        .then(|| crate::sse::query_values(req.url(), "token").next().map(|t| t.to_string()))
+        .flatten()
+    });
+  let Some(token) = token else {
+    return Err((401, "Unauthorized"));
+  };
+  let state = app.state::<AppState>();
+  let valid = state.pairing.lock().map(|p| p.check_token(&token)).unwrap_or(false);
+  if valid { Ok(()) } else { Err((401, "Unauthorized")) }
+}
+
+fn add_batch(app: &AppHandle, batch: AddBatchReq) -> (u16, String) {
+  let shared_dir = crate::queue::batch_dest_dir(batch.dest_dir, batch.category);
+  let requests = batch
+    .entries
+    .into_iter()
+    .map(|e| DownloadRequest {
+      url: e.url,
+      threads: crate::queue::DEFAULT_THREADS,
+      dest_dir: e.dest_dir.or_else(|| shared_dir.clone()),
+      file_name: e.file_name,
+      expected_checksum: None,
+      options: e.options,
+      resource: None,
+    })
+    .collect();
+  match crate::queue::enqueue_batch(app, requests) {
+    Ok(result) => (200, serde_json::to_string(&result).unwrap_or_default()),
+    Err(e) => (500, serde_json::json!({ "error": e }).to_string()),
+  }
+}
+
+/// Pairing handshake: an allow-listed extension receives the token; an unknown
+/// one is reported to the UI so the user can approve it, then polls again.
+fn pair(app: &AppHandle, origin: Option<&str>) -> (u16, String) {
+  let Some(extension_id) = origin.and_then(crate::pairing::extension_id) else {
+    return (403, r#"{"error":"pairing is only available to browser extensions"}"#.into());
+  };
+  let state = app.state::<AppState>();
+  let Ok(pairing) = state.pairing.lock() else {
+    return (500, r#"{"error":"state poisoned"}"#.into());
+  };
+  if pairing.allowed_extensions.iter().any(|a| a == extension_id) {
+    return (200, serde_json::json!({ "token": pairing.token }).to_string());
+  }
+  drop(pairing);
+  let _ = app.emit("bridge-pair-request", PairRequestPayload { extension_id: extension_id.to_string() });
+  (202, r#"{"status":"pending"}"#.into())
+}
+
+fn json_response(status: u16, body: String) -> Response<std::io::Cursor<Vec<u8>>> {
+  Response::from_string(body)
+    .with_status_code(StatusCode(status))
+    .with_header(Header::from_bytes(b"Content-Type", b"application/json").unwrap())
+}
+
+fn with_cors<R: Read>(resp: Response<R>, origin: Option<&str>) -> Response<R> {
+  let resp = resp
+    .with_header(Header::from_bytes(b"Access-Control-Allow-Headers", b"Content-Type, Authorization, X-ADM-Token").unwrap())
+    .with_header(Header::from_bytes(b"Access-Control-Allow-Methods", b"GET, POST, DELETE, OPTIONS").unwrap());
+  match origin.and_then(|o| Header::from_bytes(b"Access-Control-Allow-Origin", o.as_bytes()).ok()) {
+    Some(h) => resp.with_header(h).with_header(Header::from_bytes(b"Vary", b"Origin").unwrap()),
+    None => resp,
+  }
+}
@@ -0,0 +1,73 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::engine::conflict::ConflictPolicy;
+use crate::retry::RetryPolicy;
+use crate::util::AppDirs;
+
+/// Backend settings persisted as JSON in the app config directory.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Settings {
+    /// How many downloads may transfer at the same time; queued items wait for a free slot.
+    pub max_active_downloads: usize,
+    /// Retries per segment after transient network/server errors before the download fails.
+    pub segment_max_retries: u32,
+    pub retry_base_delay_ms: u64,
+    pub retry_max_delay_ms: u64,
+    /// Combined throughput cap for all downloads in bytes per second; 0 means unlimited.
+    pub global_speed_limit: u64,
+    /// Address and preferred port of the localhost bridge; the next free port is
+    /// used when this one is taken. Changes apply on the next start.
+    pub bridge_address: String,
+    pub bridge_port: u16,
+    /// What a download does when a file with its name already exists.
+    pub conflict_policy: ConflictPolicy,
+}
+
+impl Settings {
+    pub fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_retries: self.segment_max_retries,
+            base_delay: Duration::from_millis(self.retry_base_delay_ms),
+            max_delay: Duration::from_millis(self.retry_max_delay_ms),
+        }
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            max_active_downloads: 3,
+            segment_max_retries: 5,
+            retry_base_delay_ms: 1000,
+            retry_max_delay_ms: 30_000,
+            global_speed_limit: 0,
+            bridge_address: "127.0.0.1".into(),
+            bridge_port: 47891,
+            conflict_policy: ConflictPolicy::default(),
+        }
+    }
+}
+
+fn settings_path(dirs: &AppDirs) -> Option<std::path::PathBuf> {
+    dirs.config.as_ref().map(|d| d.join("settings.json"))
+}
+
+pub fn load(dirs: &AppDirs) -> Settings {
+    settings_path(dirs)
+        .and_then(|p| std::fs::read(p).ok())
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+pub fn save(dirs: &AppDirs, settings: &Settings) -> Result<(), String> {
+    let path = settings_path(dirs).ok_or_else(|| "Cannot resolve config directory".to_string())?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("Create dir error: {}", e))?;
+    }
+    let json =
+        serde_json::to_vec_pretty(settings).map_err(|e| format!("Serialize error: {}", e))?;
+    std::fs::write(&path, json).map_err(|e| format!("Write error: {}", e))
+}
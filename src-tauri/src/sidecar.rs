@@ -0,0 +1,104 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::request::RequestOptions;
+use crate::util::AppDirs;
+
+/// One byte range `[start, end]` of the target file and how many bytes of it
+/// have already been written to the `.part` file.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SegmentRecord {
+    pub start: u64,
+    pub end: u64,
+    pub done: u64,
+}
+
+impl SegmentRecord {
+    pub fn is_complete(&self) -> bool {
+        self.start + self.done > self.end
+    }
+}
+
+/// Persisted state of an unfinished download, stored next to the app data so
+/// a transfer can be resumed after a crash or restart.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Sidecar {
+    pub id: String,
+    pub url: String,
+    pub dest: PathBuf,
+    pub temp: PathBuf,
+    pub total: Option<u64>,
+    pub accept_ranges: bool,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// Parallel connections to use; segments may outnumber it once ranges get split.
+    #[serde(default)]
+    pub connections: usize,
+    /// `algo:hex` digest to verify once the download completes.
+    #[serde(default)]
+    pub expected_checksum: Option<String>,
+    /// Cookies, referrer and other browser context needed to fetch the file again.
+    #[serde(default)]
+    pub options: RequestOptions,
+    pub segments: Vec<SegmentRecord>,
+}
+
+impl Sidecar {
+    pub fn received(&self) -> u64 {
+        self.segments
+            .iter()
+            .map(|s| s.done.min(s.end + 1 - s.start))
+            .sum()
+    }
+}
+
+fn sidecar_dir(dirs: &AppDirs) -> Option<PathBuf> {
+    dirs.data.as_ref().map(|d| d.join("downloads"))
+}
+
+fn sidecar_path(dirs: &AppDirs, id: &str) -> Option<PathBuf> {
+    sidecar_dir(dirs).map(|d| d.join(format!("{}.json", id)))
+}
+
+pub fn save(dirs: &AppDirs, sidecar: &Sidecar) -> Result<(), String> {
+    let path = sidecar_path(dirs, &sidecar.id)
+        .ok_or_else(|| "Cannot resolve data directory".to_string())?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("Create dir error: {}", e))?;
+    }
+    let json = serde_json::to_vec_pretty(sidecar).map_err(|e| format!("Serialize error: {}", e))?;
+    // Write to a scratch file first so a crash never leaves a truncated sidecar behind
+    let mut tmp = path.clone();
+    tmp.set_extension("json.tmp");
+    std::fs::write(&tmp, json).map_err(|e| format!("Write error: {}", e))?;
+    std::fs::rename(&tmp, &path).map_err(|e| format!("Rename error: {}", e))?;
+    Ok(())
+}
+
+pub fn load(dirs: &AppDirs, id: &str) -> Result<Sidecar, String> {
+    let path = sidecar_path(dirs, id).ok_or_else(|| "Cannot resolve data directory".to_string())?;
+    let bytes =
+        std::fs::read(&path).map_err(|_| format!("No resumable download with id {}", id))?;
+    serde_json::from_slice(&bytes).map_err(|e| format!("Corrupt sidecar for {}: {}", id, e))
+}
+
+/// Every sidecar on disk, i.e. all paused or interrupted downloads.
+pub fn load_all(dirs: &AppDirs) -> Vec<Sidecar> {
+    let Some(entries) = sidecar_dir(dirs).and_then(|d| std::fs::read_dir(d).ok()) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|p| std::fs::read(p).ok())
+        .filter_map(|bytes| serde_json::from_slice(&bytes).ok())
+        .collect()
+}
+
+pub fn remove(dirs: &AppDirs, id: &str) {
+    if let Some(path) = sidecar_path(dirs, id) {
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::HashSet;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tauri::{AppHandle, Listener};
+use tiny_http::Request;
+
+/// Events rebroadcast to SSE clients with exactly the payload the webviews get.
+const FORWARDED_EVENTS: [&str; 5] = [
+    "download_started",
+    "download_progress",
+    "download_completed",
+    "download_failed",
+    "download_canceled",
+];
+
+/// A comment line keeps idle connections (and proxies) from timing out.
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+struct Subscriber {
+    /// Only events for these download ids; `None` means all of them.
+    ids: Option<HashSet<String>>,
+    tx: Sender<String>,
+    /// Set when the client's connection thread has ended.
+    closed: Arc<AtomicBool>,
+}
+
+/// Fan-out of download events to every connected `text/event-stream` client.
+#[derive(Default)]
+pub struct EventHub {
+    subscribers: Mutex<Vec<Subscriber>>,
+}
+
+impl EventHub {
+    /// Create a hub fed by the app's own download events.
+    pub fn attach(app: &AppHandle) -> Arc<Self> {
+        let hub = Arc::new(Self::default());
+        for name in FORWARDED_EVENTS {
+            let hub = hub.clone();
+            app.listen_any(name, move |event| hub.publish(name, event.payload()));
+        }
+        hub
+    }
+
+    fn publish(&self, name: &str, payload: &str) {
+        let Ok(mut subscribers) = self.subscribers.lock() else {
+            return;
+        };
+        if subscribers.is_empty() {
+            return;
+        }
+        let id = serde_json::from_str::<serde_json::Value>(payload)
+            .ok()
+            .and_then(|v| v.get("id")?.as_str().map(|s| s.to_string()));
+        let frame = format!("event: {}\ndata: {}\n\n", name, payload);
+        // A failed send means the client's connection thread has gone away;
+        // `closed` catches those that no event was meant for
+        subscribers.retain(|s| {
+            if s.closed.load(Ordering::Relaxed) {
+                return false;
+            }
+            let wanted = match (&s.ids, &id) {
+                (None, _) => true,
+                (Some(ids), Some(id)) => ids.contains(id),
+                (Some(_), None) => false,
+            };
+            !wanted || s.tx.send(frame.clone()).is_ok()
+        });
+    }
+
+    /// Stream events to `req` on its own thread until the client disconnects.
+    /// `?id=a,b` (or repeated `id=`) limits the stream to those downloads.
+    pub fn serve(&self, req: Request, cors_origin: Option<String>) {
+        let ids: HashSet<String> = query_values(req.url(), "id")
+            .flat_map(|v| {
+                v.split(',')
+                    .map(|s| s.trim().to_string())
+                    .collect::<Vec<_>>()
+            })
+            .filter(|s| !s.is_empty())
+            .collect();
+        let (tx, rx) = mpsc::channel();
+        let closed = Arc::new(AtomicBool::new(false));
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            subscribers.push(Subscriber {
+                ids: (!ids.is_empty()).then_some(ids),
+                tx,
+                closed: closed.clone(),
+            });
+        }
+        std::thread::spawn(move || {
+            stream(req, cors_origin, rx);
+            closed.store(true, Ordering::Relaxed);
+        });
+    }
+}
+
+/// Write frames from `rx` to the client until it disconnects; keep-alives
+/// notice a dead connection even when no events come.
+fn stream(req: Request, cors_origin: Option<String>, rx: Receiver<String>) {
+    // tiny_http would buffer a streamed body, so write the response by hand
+    let mut writer = req.into_writer();
+    let mut head = String::from(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: keep-alive\r\n",
+    );
+    if let Some(origin) = cors_origin {
+        head.push_str(&format!(
+            "Access-Control-Allow-Origin: {}\r\nVary: Origin\r\n",
+            origin
+        ));
+    }
+    head.push_str("\r\n: connected\n\n");
+    if writer
+        .write_all(head.as_bytes())
+        .and_then(|_| writer.flush())
+        .is_err()
+    {
+        return;
+    }
+    loop {
+        let frame = match rx.recv_timeout(KEEP_ALIVE) {
+            Ok(frame) => frame,
+            Err(RecvTimeoutError::Timeout) => ": keep-alive\n\n".to_string(),
+            Err(RecvTimeoutError::Disconnected) => return,
+        };
+        if writer
+            .write_all(frame.as_bytes())
+            .and_then(|_| writer.flush())
+            .is_err()
+        {
+            return;
+        }
+    }
+}
+
+/// Values of `key` in the query string of a request path.
+pub fn query_values<'a>(url: &'a str, key: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+    url.split_once('?')
+        .map(|(_, query)| query)
+        .unwrap_or_default()
+        .split('&')
+        .filter_map(move |pair| match pair.split_once('=') {
+            Some((k, v)) if k == key => Some(v),
+            _ => None,
+        })
+}
@@ -1,17 +1,73 @@
 use std::collections::HashMap;
 use std::path::PathBuf;
-use std::sync::{Arc, Mutex, atomic::AtomicBool};
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::oneshot;
+
+use crate::engine::conflict::ConflictAnswer;
+use crate::limiter::{RateLimiter, Throttle};
+use crate::pairing::BridgePairing;
+use crate::payloads::{BridgeAddPayload, BridgeStatus};
+use crate::queue::DownloadQueue;
+use crate::registry::Registry;
+use crate::settings::Settings;
+use crate::util::AppDirs;
 
 pub struct AppState {
-    pub cancels: Mutex<HashMap<String, Arc<AtomicBool>>>,
-    pub metas: Mutex<HashMap<String, DownloadMeta>>,
+    pub downloads: Mutex<Registry>,
+    pub queue: Mutex<DownloadQueue>,
+    pub settings: Mutex<Settings>,
+    pub global_limiter: Arc<RateLimiter>,
+    pub limiters: Mutex<HashMap<String, Arc<RateLimiter>>>,
+    pub pairing: Mutex<BridgePairing>,
+    pub bridge: Mutex<BridgeStatus>,
+    /// Downloads waiting for an answer to `download_conflict`.
+    pub conflicts: Mutex<HashMap<String, oneshot::Sender<ConflictAnswer>>>,
+    /// Links received before the main window was ready; `None` once it took them.
+    pub pending_adds: Mutex<Option<Vec<BridgeAddPayload>>>,
+    pub dirs: AppDirs,
 }
 
 impl Default for AppState {
     fn default() -> Self {
+        Self::new(AppDirs::user())
+    }
+}
+
+impl AppState {
+    /// State loaded from, and later saved to, `dirs`.
+    pub fn new(dirs: AppDirs) -> Self {
+        let settings = crate::settings::load(&dirs);
         Self {
-            cancels: Mutex::new(HashMap::new()),
-            metas: Mutex::new(HashMap::new()),
+            downloads: Mutex::new(Registry::load(&dirs)),
+            queue: Mutex::new(DownloadQueue::load(&dirs)),
+            global_limiter: Arc::new(RateLimiter::new(settings.global_speed_limit)),
+            settings: Mutex::new(settings),
+            limiters: Mutex::new(HashMap::new()),
+            pairing: Mutex::new(BridgePairing::load(&dirs)),
+            bridge: Mutex::new(BridgeStatus::default()),
+            conflicts: Mutex::new(HashMap::new()),
+            pending_adds: Mutex::new(Some(Vec::new())),
+            dirs,
+        }
+    }
+
+    /// Limiter for one download, created unlimited the first time it is asked for
+    /// so a limit can be set before the download starts.
+    pub fn limiter_for(&self, id: &str) -> Arc<RateLimiter> {
+        match self.limiters.lock() {
+            Ok(mut map) => map
+                .entry(id.to_string())
+                .or_insert_with(|| Arc::new(RateLimiter::new(0)))
+                .clone(),
+            Err(_) => Arc::new(RateLimiter::new(0)),
+        }
+    }
+
+    pub fn throttle_for(&self, id: &str) -> Throttle {
+        Throttle {
+            download: self.limiter_for(id),
+            global: self.global_limiter.clone(),
         }
     }
 }
@@ -23,4 +79,6 @@ pub struct DownloadMeta {
     pub temp: PathBuf,
     pub total: Option<u64>,
     pub accept_ranges: bool,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
 }
@@ -34,3 +34,78 @@ pub fn guess_category_by_ext(name: &str) -> String {
     }
     "other".into()
 }
+
+/// Where the app keeps its files, held in [`crate::state::AppState`] so tests
+/// can use a scratch directory instead of the user's.
+#[derive(Clone)]
+pub struct AppDirs {
+    /// App-managed data: sidecars, the queue, the bridge endpoint.
+    pub data: Option<std::path::PathBuf>,
+    /// Settings and the bridge pairing.
+    pub config: Option<std::path::PathBuf>,
+}
+
+impl AppDirs {
+    /// The platform's per-user directories.
+    pub fn user() -> Self {
+        Self {
+            data: dirs::data_dir().map(|d| d.join("any-download-manager")),
+            config: dirs::config_dir().map(|d| d.join("any-download-manager")),
+        }
+    }
+}
+
+/// The file name and directory shown for a download saved at `dest`.
+pub fn split_dest(dest: &std::path::Path) -> (String, String) {
+    let file_name = dest
+        .file_name()
+        .and_then(|s| s.to_str())
+        .unwrap_or("download.bin")
+        .to_string();
+    let dest_dir = dest
+        .parent()
+        .unwrap_or(std::path::Path::new(""))
+        .to_string_lossy()
+        .to_string();
+    (file_name, dest_dir)
+}
+
+/// Unix time in milliseconds.
+pub fn now_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// Millisecond-based download id that stays unique even when many downloads
+/// are created within the same millisecond.
+pub fn new_download_id() -> String {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    static LAST: AtomicU64 = AtomicU64::new(0);
+    let now = now_ms();
+    let mut prev = LAST.load(Ordering::Relaxed);
+    loop {
+        let next = now.max(prev + 1);
+        match LAST.compare_exchange(prev, next, Ordering::Relaxed, Ordering::Relaxed) {
+            Ok(_) => return format!("dl-{}", next),
+            Err(actual) => prev = actual,
+        }
+    }
+}
+
+/// Every http(s) link in a pasted text or link-list file, one per whitespace
+/// separated token. Lines starting with `#` are comments.
+pub fn extract_links(text: &str) -> Vec<String> {
+    text.lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .flat_map(|line| line.split_whitespace())
+        .map(|token| {
+            token
+                .trim_start_matches(['<', '(', '"', '\''])
+                .trim_end_matches(['>', ')', '"', '\'', ',', ';'])
+        })
+        .filter(|token| token.starts_with("http://") || token.starts_with("https://"))
+        .map(|token| token.to_string())
+        .collect()
+}
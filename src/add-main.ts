@@ -7,6 +7,23 @@ import { applyThemeToDocument, getSavedTheme } from './ui/settings';
 import { invoke } from '@tauri-apps/api/core';
 import { getCurrentWindow } from '@tauri-apps/api/window';
 import { listen } from '@tauri-apps/api/event';
+import type { AddPrefill } from './ui/addWindow';
+
+/** What `probe_url` found out; handed back to the download so it isn't probed twice. */
+interface ProbeResult {
+  url: string;
+  final_url: string;
+  total: number | null;
+  accept_ranges: boolean;
+  etag: string | null;
+  last_modified: string | null;
+  mime_type: string | null;
+  file_name: string;
+  server: string | null;
+  probed_at: number;
+  category: string;
+  download_dir: string;
+}
 
 function formatBytes(bytes?: number | null): string {
   if (!Number.isFinite(bytes as number) || (bytes as number) <= 0) return '—';
@@ -55,24 +72,43 @@ function joinPath(dir: string, sub: string): string {
     try { await getCurrentWindow().close(); } catch {}
   };
 
-  // If launched with ?url=... prefill and probe
+  // Browser context (cookies, referrer, ...) sent by the extension for one specific link
+  let bridgeUrl = '';
+  let bridgeOptions: Record<string, unknown> | undefined;
+  let bridgeDestDir = '';
+
+  function requestOptionsFor(url: string): Record<string, unknown> | undefined {
+    return url === bridgeUrl ? bridgeOptions : undefined;
+  }
+
+  function applyPrefill(prefill: AddPrefill | string) {
+    const p: AddPrefill = typeof prefill === 'string' ? { url: prefill } : prefill;
+    const link = (p.url || '').trim();
+    if (!link) return;
+    urlInput.value = link;
+    bridgeUrl = link;
+    bridgeOptions = p.options;
+    bridgeDestDir = (p.dest_dir || '').trim();
+    nameInput.value = (p.file_name || '').trim();
+    if (bridgeDestDir) pathInput.value = bridgeDestDir;
+    void probeAndFill();
+  }
+
+  // If launched with ?url=... or ?prefill=... prefill and probe
   try {
-    const initial = new URLSearchParams(location.search).get('url');
+    const params = new URLSearchParams(location.search);
+    const prefill = params.get('prefill');
+    const initial = prefill ? (JSON.parse(prefill) as AddPrefill) : params.get('url');
     if (initial) {
-      urlInput.value = initial;
       // defer to ensure DOM is ready
-      setTimeout(() => { void probeAndFill(); }, 0);
+      setTimeout(() => { applyPrefill(initial); }, 0);
     }
   } catch {}
 
   // Listen for prefill event from main window
   try {
-    void listen<string>('adm-prefill-url', (evt) => {
-      const link = (evt.payload || '').trim();
-      if (link) {
-        urlInput.value = link;
-        void probeAndFill();
-      }
+    void listen<AddPrefill | string>('adm-prefill-url', (evt) => {
+      if (evt.payload) applyPrefill(evt.payload);
     });
   } catch {}
 
@@ -94,6 +130,7 @@ function joinPath(dir: string, sub: string): string {
   // Keep last detected info from probe
   let lastDetectedCat: string = 'other';
   let lastDownloadDir: string = '';
+  let lastProbe: ProbeResult | null = null;
 
   function updateCategoryLabel() {
     const val = getCategory();
@@ -139,8 +176,10 @@ function joinPath(dir: string, sub: string): string {
       return;
     }
     sizeEl.textContent = '…';
+    lastProbe = null;
     try {
-      const res = await invoke<{ total: number | null; file_name: string; category: string; download_dir: string }>('probe_url', { url });
+      const res = await invoke<ProbeResult>('probe_url', { url, options: requestOptionsFor(url) });
+      lastProbe = res;
       lastDetectedCat = (res.category || 'other');
       lastDownloadDir = (res.download_dir || '');
       // Category: prefer existing selection if user already changed it, otherwise from probe or stored prefer
@@ -157,7 +196,9 @@ function joinPath(dir: string, sub: string): string {
       // If empty OR current path doesn't match chosen category, update it
       const cur = (pathInput.value || '').trim();
       const shouldUpdate = !cur || !cur.replace(/\\/g, '/').endsWith(`/${catForPath}`);
-      if (shouldUpdate) pathInput.value = joinPath(base, catForPath);
+      // A folder chosen by the extension wins over the category folder
+      if (url === bridgeUrl && bridgeDestDir) pathInput.value = bridgeDestDir;
+      else if (shouldUpdate) pathInput.value = joinPath(base, catForPath);
       if (!nameInput.value) {
         nameInput.value = res.file_name || 'download.bin';
       }
@@ -242,8 +283,9 @@ function joinPath(dir: string, sub: string): string {
     const fileName = (nameInput.value || '').trim() || 'download.bin';
     startBtn.disabled = true;
     try {
-      // Start download and close window immediately
-      void invoke<string>('start_download', { url, threads, destDir, fileName }).catch((err) => {
+      // Queue download (starts as soon as a slot is free) and close window immediately
+      const resource = lastProbe?.url === url ? lastProbe : null;
+      void invoke<string>('enqueue_download', { url, threads, destDir, fileName, options: requestOptionsFor(url), resource }).catch((err) => {
         console.error(err);
       });
       await closeWindow();
@@ -8,11 +8,10 @@ import { wireWindowControls } from './ui/windowControls';
 import { openSettingsWindow } from './ui/settingsWindow';
 import { applyThemeToDocument, getSavedTheme } from './ui/settings';
 import { initI18n } from './ui/i18n';
-import { openAddWindow } from './ui/addWindow';
+import { openAddWindow, type AddPrefill } from './ui/addWindow';
 import { initDownloadsUI } from './ui/downloads';
-// Deep link listener
-import { onOpenUrl } from '@tauri-apps/plugin-deep-link';
 import { listen } from '@tauri-apps/api/event';
+import { invoke } from '@tauri-apps/api/core';
 
 (function mount(){
   const app = document.createElement('div');
@@ -52,32 +51,47 @@ import { listen } from '@tauri-apps/api/event';
     e.preventDefault();
   }, { capture: true });
 
-  // 监听系统深链，例如 adm://add?url=...
+  // 监听本地桥事件（避免 Chrome 外部应用确认）；adm:// 深链和命令行参数由后端解析后走同一事件
+  const openPrefill = async (prefill?: AddPrefill) => {
+    const link = (prefill?.url || '').trim();
+    if (link) {
+      await openAddWindow(link, { ...prefill, url: link });
+    }
+  };
   try {
-    void onOpenUrl(async (urls: string[]) => {
-      // urls: string[]
-      for (const u of urls) {
-        try {
-          const parsed = new URL(u);
-          if (parsed.protocol === 'adm:' && parsed.host === 'add') {
-            const link = parsed.searchParams.get('url') || '';
-            if (link) {
-              await openAddWindow(link);
-              break;
-            }
-          }
-        } catch {}
+    void listen<AddPrefill>('adm-add-from-bridge', (evt) => openPrefill(evt.payload))
+      .then(() => invoke<AddPrefill[]>('take_pending_adds'))
+      .then(async (pending) => {
+        // 冷启动时收到的链接
+        for (const p of pending) await openPrefill(p);
+      })
+      .catch(() => {});
+  } catch {}
+
+  // 浏览器扩展请求配对：用户确认后加入白名单，扩展再次请求 /pair 即可拿到令牌
+  try {
+    const asking = new Set<string>();
+    void listen<{ extension_id: string }>('bridge-pair-request', async (evt) => {
+      const id = evt.payload?.extension_id;
+      // 扩展会轮询 /pair，同一个扩展只弹一次确认框
+      if (!id || asking.has(id)) return;
+      asking.add(id);
+      try {
+        if (window.confirm(`Allow browser extension ${id} to send downloads to this app?`)) {
+          await invoke('allow_bridge_extension', { extensionId: id });
+        }
+      } finally {
+        asking.delete(id);
       }
     });
   } catch {}
 
-  // 监听本地桥事件（避免 Chrome 外部应用确认）
+  // 目标文件已存在（冲突策略为 ask）：确定则覆盖，取消则另存为 “name (1).ext”
   try {
-    void listen<string>('adm-add-from-bridge', async (evt) => {
-      const link = (evt.payload || '').trim();
-      if (link) {
-        await openAddWindow(link);
-      }
+    void listen<{ id: string; path: string; size: number; total: number | null }>('download_conflict', async (evt) => {
+      const { id, path } = evt.payload;
+      const answer = window.confirm(`${path} already exists. Replace it?\n\nCancel keeps both files.`) ? 'overwrite' : 'rename';
+      await invoke('resolve_conflict', { id, answer }).catch((e) => console.error(e));
     });
   } catch {}
 })();
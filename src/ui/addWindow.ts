@@ -6,10 +6,22 @@ function isTauriEnv(): boolean {
   return typeof (window as any).__TAURI__ !== 'undefined' || typeof (window as any).__TAURI_INTERNALS__ !== 'undefined';
 }
 
-export async function openAddWindow(initialUrl?: string): Promise<void> {
+/** Link handed over by the browser extension, including the session context needed to fetch it. */
+export interface AddPrefill {
+  url: string;
+  file_name?: string | null;
+  dest_dir?: string | null;
+  options?: Record<string, unknown>;
+}
+
+function addPageUrl(initialUrl?: string, prefill?: AddPrefill): string {
+  if (prefill) return `add.html?prefill=${encodeURIComponent(JSON.stringify(prefill))}`;
+  return initialUrl ? `add.html?url=${encodeURIComponent(initialUrl)}` : 'add.html';
+}
+
+export async function openAddWindow(initialUrl?: string, prefill?: AddPrefill): Promise<void> {
   if (!isTauriEnv()) {
-    const url = initialUrl ? `add.html?url=${encodeURIComponent(initialUrl)}` : 'add.html';
-    window.open(url, '_blank');
+    window.open(addPageUrl(initialUrl, prefill), '_blank');
     return;
   }
 
@@ -18,8 +30,8 @@ export async function openAddWindow(initialUrl?: string): Promise<void> {
     try { await (existing as any).unminimize?.(); } catch {}
     try { await existing.show(); } catch {}
     try { await existing.setFocus(); } catch {}
-    if (initialUrl) {
-      try { await emitTo('add', 'adm-prefill-url', initialUrl); } catch {}
+    if (prefill || initialUrl) {
+      try { await emitTo('add', 'adm-prefill-url', prefill ?? initialUrl); } catch {}
     }
     return;
   }
@@ -54,7 +66,7 @@ export async function openAddWindow(initialUrl?: string): Promise<void> {
 
   // @ts-ignore
   const win = new WebviewWindow('add', {
-    url: initialUrl ? `add.html?url=${encodeURIComponent(initialUrl)}` : 'add.html',
+    url: addPageUrl(initialUrl, prefill),
     title: '添加下载',
     width: targetWidth,
     height: targetHeight,
@@ -17,7 +17,26 @@ interface ProgressPayload {
   speed: number;
 }
 interface CompletedPayload { id: string; path: string }
-interface FailedPayload { id: string; error: string }
+interface DownloadError {
+  kind: string; // e.g. 'network', 'http_status', 'disk_full', 'checksum_mismatch'
+  retryable: boolean;
+  message: string;
+  details?: Record<string, unknown>;
+}
+interface FailedPayload { id: string; error: DownloadError }
+interface DownloadRecord {
+  id: string;
+  url: string;
+  file_name: string;
+  dest_dir: string;
+  total: number | null;
+  received: number;
+  status: 'queued' | 'probing' | 'downloading' | 'paused' | 'verifying' | 'completed' | 'failed' | 'canceled';
+  error?: DownloadError;
+  created_at: number; // epoch ms
+  updated_at: number;
+  finished_at?: number;
+}
 
 type RowRefs = {
   row: HTMLDivElement;
@@ -295,10 +314,11 @@ function markCompleted(id: string, path: string) {
   });
 }
 
-function markFailed(id: string, _error: string) {
+function markFailed(id: string, error?: DownloadError) {
   const refs = rows.get(id);
   if (!refs) return;
   refs.status.innerHTML = `<div class="badge danger" data-i18n="table.status.failed">${t('table.status.failed')}</div>`;
+  if (error) refs.status.title = error.message;
   refs.speed.textContent = '—';
   // keep size as-is
   refs.lastSpeed = 0;
@@ -331,7 +351,7 @@ export async function initDownloadsUI() {
     if (h.status === 'finished' && h.path) {
       markCompleted(h.id, h.path);
     } else if (h.status === 'failed') {
-      markFailed(h.id, '');
+      markFailed(h.id);
     }
   });
 
@@ -361,10 +381,37 @@ export async function initDownloadsUI() {
   // failed
   unlistenFns.push(await listen<FailedPayload>('download_failed', (e) => markFailed(e.payload.id, e.payload.error)));
   // Note: canceled events are ignored in UI (no pause feature)
+  await syncWithBackend();
   // initialize status bar to current state
   updateStatusBar();
 }
 
+// Downloads that started before this page (re)loaded are only known to the backend
+async function syncWithBackend() {
+  let records: DownloadRecord[];
+  try {
+    records = await invoke<DownloadRecord[]>('list_downloads');
+  } catch (e) {
+    console.error(e);
+    return;
+  }
+  records.filter(r => r.status !== 'queued' && r.status !== 'canceled').forEach((r) => {
+    if (!rows.has(r.id)) {
+      const refs = createRow({ id: r.id, url: r.url, file_name: r.file_name, total: r.total, dest_dir: r.dest_dir }, 'top');
+      rows.set(r.id, refs);
+      refs.createdAt = r.created_at;
+      scheduleRowDateUpdate(refs);
+    }
+    if (r.status === 'completed') {
+      markCompleted(r.id, safeJoin(r.dest_dir, r.file_name) || '');
+    } else if (r.status === 'failed') {
+      markFailed(r.id, r.error);
+    } else {
+      updateProgress({ id: r.id, received: r.received, total: r.total ?? 0, speed: 0 });
+    }
+  });
+}
+
 export function disposeDownloadsUI() {
   unlistenFns.forEach((fn) => fn());
   unlistenFns = [];